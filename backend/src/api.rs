@@ -1,99 +1,683 @@
+use crate::commands;
+use crate::export::{self, ChannelWriter, Export, ExportError, ExportFilter, ExportFormat};
+use crate::peers::Peer;
+use crate::recorder::{self, RecorderError, RecorderStatus, RecordingInfo};
+use crate::replay::ReplayError;
 use crate::state::AppState;
+use crate::ws::ws_handler;
 use axum::{
-    extract::{ws::{Message, WebSocket, WebSocketUpgrade}, State},
-    response::IntoResponse,
-    routing::get,
-    Router,
+    body::{Body, Bytes},
+    extract::{
+        rejection::{JsonRejection, QueryRejection},
+        Path, Query, State,
+    },
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
 };
-use futures::{sink::SinkExt, stream::StreamExt};
-
-use tracing::{error, info};
+use serde::{Deserialize, Serialize};
+use shared::Timestamp;
+use shared::proto::SensorReading;
+use shared::ws::{ClockStats, JsonFrame, NodeInfo, ReplayControl, ReplayStatus, StreamStats};
+use shared::{MessageKind, MessageWrapper};
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+use tracing::warn;
 
 pub fn app_router(state: AppState) -> Router {
     Router::new()
         .route("/ws", get(ws_handler))
+        .route("/api/state", get(latest_state))
+        .route("/api/sensors", get(list_sensors))
+        .route("/api/sensors/:id", get(get_sensor))
+        .route("/api/nodes", get(list_nodes))
+        .route("/api/history", get(history))
+        .route("/api/commands/actuator", post(commands::actuator))
+        .route("/api/commands/clock", post(commands::clock))
+        .route("/api/commands/faults", post(commands::fault))
+        .route("/api/commands/tests", post(commands::test))
+        .route("/api/recorder", get(recorder_status))
+        .route("/api/recorder/start", post(start_recording))
+        .route("/api/recorder/stop", post(stop_recording))
+        .route("/api/recordings", get(list_recordings))
+        .route("/api/recordings/:name/export", get(export_recording))
+        .route("/api/replay", get(replay_status).post(control_replay))
+        .route("/api/stats/drops", get(drop_stats))
+        .route("/api/stats/sequence", get(sequence_stats))
+        .route("/api/stats/clock", get(clock_stats))
+        .route("/api/peers", get(list_peers))
         .with_state(state)
 }
 
-async fn ws_handler(
-    ws: WebSocketUpgrade,
-    State(state): State<AppState>,
-) -> impl IntoResponse {
-    ws.on_upgrade(|socket| handle_socket(socket, state))
+/// JSON error body, `{"error": "..."}`, with its status code.
+pub struct ApiError(StatusCode, String);
+
+impl ApiError {
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self(StatusCode::BAD_REQUEST, message.into())
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self(StatusCode::NOT_FOUND, message.into())
+    }
+
+    pub fn unavailable(message: impl Into<String>) -> Self {
+        Self(StatusCode::SERVICE_UNAVAILABLE, message.into())
+    }
 }
 
-async fn handle_socket(socket: WebSocket, state: AppState) {
-    let (mut sender, mut receiver) = socket.split();
-    let mut rx = state.tx.subscribe();
+impl From<RecorderError> for ApiError {
+    fn from(e: RecorderError) -> Self {
+        let status = match e {
+            RecorderError::AlreadyRecording(_) | RecorderError::NotRecording | RecorderError::Exists(_) => {
+                StatusCode::CONFLICT
+            }
+            RecorderError::InvalidName(_) => StatusCode::BAD_REQUEST,
+            RecorderError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self(status, e.to_string())
+    }
+}
 
-    // Send latest values to the new client
-    for entry in state.latest_values.iter() {
-        let msg = entry.value();
-        if let Ok(bytes) = msg.to_bytes() {
-             if let Err(e) = sender.send(Message::Binary(bytes)).await {
-                error!("Error sending initial state: {}", e);
-                return;
+impl From<ReplayError> for ApiError {
+    fn from(e: ReplayError) -> Self {
+        let status = match e {
+            ReplayError::NotFound(_) => StatusCode::NOT_FOUND,
+            ReplayError::NotReplaying => StatusCode::CONFLICT,
+            ReplayError::Empty(_) | ReplayError::InvalidSpeed(_) | ReplayError::Target(..) => {
+                StatusCode::BAD_REQUEST
             }
-        }
+            ReplayError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self(status, e.to_string())
+    }
+}
+
+impl From<ExportError> for ApiError {
+    fn from(e: ExportError) -> Self {
+        let status = match e {
+            ExportError::NotFound(_) => StatusCode::NOT_FOUND,
+            ExportError::Io(_) | ExportError::Parquet(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self(status, e.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
     }
+}
 
-    // Spawn a task to forward broadcast messages to this client
-    let mut send_task = tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
-            // Serialize message to JSON for WebSocket
-            // Note: In a real high-perf scenario, we might want to use binary (Protobuf) over WS too.
-            // But for web frontend ease, JSON is often preferred unless performance is critical.
-            // Given the requirements mention "Receive packets and deserialize them using shared types (Protobuf)",
-            // but doesn't explicitly say WS must be Protobuf. Let's stick to JSON for now as it's easier for Leptos/JS.
-            // Wait, shared types are Protobuf generated. They might not implement Serialize/Deserialize by default unless configured.
-            // Let's check if `shared` crate enables serde for prost types.
-            // The `prost-build` config in `shared/build.rs` (which I can't see but assume) or `prost` attributes need to be set.
-            // If not, we might need to send binary.
-            // Let's assume binary for now to be safe and consistent with "bridge".
-            
-            match msg.to_bytes() {
-                Ok(bytes) => {
-                    if let Err(e) = sender.send(Message::Binary(bytes)).await {
-                        error!("Error sending WS message: {}", e);
-                        break;
-                    }
-                }
-                Err(e) => {
-                    error!("Error serializing message: {}", e);
-                }
+/// Latest cached payloads, per kind, one per source and sensor/actuator/node.
+async fn latest_state(State(state): State<AppState>) -> Json<BTreeMap<MessageKind, Vec<serde_json::Value>>> {
+    let mut kinds: BTreeMap<MessageKind, Vec<serde_json::Value>> = BTreeMap::new();
+    for msg in state.latest_values.latest() {
+        let payload = match serde_json::to_value(&msg) {
+            Ok(mut value) => value["payload"].take(),
+            Err(e) => {
+                warn!("Failed to serialize latest {}: {}", msg.type_name(), e);
+                continue;
             }
+        };
+        kinds.entry(msg.kind()).or_default().push(payload);
+    }
+    Json(kinds)
+}
+
+/// Latest reading of one sensor from one source.
+#[derive(Debug, Serialize)]
+struct SensorValue {
+    source: String,
+    timestamp: Option<Timestamp>,
+    reading: SensorReading,
+}
+
+fn sensor_values(state: &AppState) -> Vec<SensorValue> {
+    state
+        .latest_values
+        .latest()
+        .into_iter()
+        .filter_map(|msg| match msg {
+            MessageWrapper::SensorBatch(batch) => Some(batch),
+            _ => None,
+        })
+        .flat_map(|batch| {
+            let header = batch.header.unwrap_or_default();
+            batch.readings.into_iter().map(move |reading| SensorValue {
+                source: header.source.clone(),
+                timestamp: header.timestamp,
+                reading,
+            })
+        })
+        .collect()
+}
+
+async fn list_sensors(State(state): State<AppState>) -> Json<Vec<SensorValue>> {
+    Json(sensor_values(&state))
+}
+
+/// Every source's latest reading of sensor `id`.
+async fn get_sensor(State(state): State<AppState>, Path(id): Path<String>) -> Result<Json<Vec<SensorValue>>, ApiError> {
+    let values: Vec<SensorValue> = sensor_values(&state)
+        .into_iter()
+        .filter(|value| value.reading.sensor_id == id)
+        .collect();
+    if values.is_empty() {
+        return Err(ApiError::not_found(format!("Unknown sensor {:?}", id)));
+    }
+    Ok(Json(values))
+}
+
+/// Realtime nodes seen through their heartbeats, with their liveness.
+async fn list_nodes(State(state): State<AppState>) -> Json<Vec<NodeInfo>> {
+    Json(state.liveness.list())
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    kind: Option<MessageKind>,
+    /// RFC 3339 time or microseconds since the Unix epoch.
+    since: Option<String>,
+    until: Option<String>,
+}
+
+/// Recent inbound messages in reception order, as JSON subprotocol frames.
+async fn history(State(state): State<AppState>, Query(query): Query<HistoryQuery>) -> Result<Json<Vec<JsonFrame>>, ApiError> {
+    let since = query.since.as_deref().map(parse_time).transpose()?;
+    let until = query.until.as_deref().map(parse_time).transpose()?;
+    let frames = state
+        .history
+        .query(query.kind, since, until)
+        .into_iter()
+        .map(|envelope| JsonFrame {
+            msg: envelope.msg,
+            meta: Some(envelope.meta),
+        })
+        .collect();
+    Ok(Json(frames))
+}
+
+fn parse_time(value: &str) -> Result<i64, ApiError> {
+    export::parse_time(value).map_err(ApiError::bad_request)
+}
+
+async fn recorder_status(State(state): State<AppState>) -> Json<RecorderStatus> {
+    Json(state.recorder.status())
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct StartRecording {
+    /// Directory name under `recorder.dir`; defaults to the UTC start time.
+    name: Option<String>,
+}
+
+/// The body is optional, but when present it must be a valid
+/// `StartRecording`.
+async fn start_recording(State(state): State<AppState>, body: Bytes) -> Result<Json<RecorderStatus>, ApiError> {
+    let request = if body.is_empty() {
+        StartRecording::default()
+    } else {
+        serde_json::from_slice(&body).map_err(|e| ApiError::bad_request(e.to_string()))?
+    };
+    Ok(Json(state.recorder.start(request.name.as_deref(), SystemTime::now())?))
+}
+
+async fn stop_recording(State(state): State<AppState>) -> Result<Json<RecorderStatus>, ApiError> {
+    // Waits for the writer to drain its queue
+    let stopped = tokio::task::spawn_blocking(move || state.recorder.stop())
+        .await
+        .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))??;
+    Ok(Json(stopped))
+}
+
+async fn list_recordings(State(state): State<AppState>) -> Result<Json<Vec<RecordingInfo>>, ApiError> {
+    recorder::list_recordings(&state.config.recorder.dir)
+        .map(Json)
+        .map_err(|e| RecorderError::Io(e).into())
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+    /// RFC 3339 time or microseconds since the Unix epoch.
+    since: Option<String>,
+    until: Option<String>,
+    /// Comma-separated sensor id glob patterns; every sensor when omitted.
+    sensors: Option<String>,
+}
+
+/// Sensor data of a recording as a CSV or Parquet download.
+async fn export_recording(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    query: Result<Query<ExportQuery>, QueryRejection>,
+) -> Result<Response, ApiError> {
+    let Query(query) = query.map_err(|e| ApiError::bad_request(e.body_text()))?;
+    let filter = ExportFilter {
+        since: query.since.as_deref().map(parse_time).transpose()?,
+        until: query.until.as_deref().map(parse_time).transpose()?,
+        sensors: query
+            .sensors
+            .iter()
+            .flat_map(|sensors| sensors.split(','))
+            .filter(|pattern| !pattern.is_empty())
+            .map(str::to_string)
+            .collect(),
+    };
+    let dir = state.config.recorder.dir.clone();
+    let format = query.format;
+    let disposition = format!("attachment; filename=\"{}.{}\"", name, format.extension());
+    // Scanned up front so a missing recording is still a JSON error
+    let export = tokio::task::spawn_blocking(move || Export::prepare(&dir, &name, filter))
+        .await
+        .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))??;
+
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
+    tokio::task::spawn_blocking(move || {
+        let mut out = ChannelWriter::new(tx.clone());
+        if let Err(e) = export.write(format, &mut out) {
+            warn!("Export failed: {}", e);
+            // Aborts the download rather than ending it as if complete
+            let _ = tx.blocking_send(Err(std::io::Error::other(e)));
         }
     });
+    let chunks = futures::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|chunk| (chunk, rx)) });
+    Ok((
+        [(header::CONTENT_TYPE, format.content_type().to_string()), (header::CONTENT_DISPOSITION, disposition)],
+        Body::from_stream(chunks),
+    )
+        .into_response())
+}
+
+/// The current or last replay; `null` before the first one.
+async fn replay_status(State(state): State<AppState>) -> Json<Option<ReplayStatus>> {
+    Json(state.replay.status())
+}
+
+async fn control_replay(
+    State(state): State<AppState>,
+    body: Result<Json<ReplayControl>, JsonRejection>,
+) -> Result<Json<ReplayStatus>, ApiError> {
+    let Json(control) = body.map_err(|e| ApiError::bad_request(e.body_text()))?;
+    Ok(Json(state.replay.control(&state, control).await?))
+}
+
+/// Counts of inbound UDP frames dropped by the codec, per reason.
+async fn drop_stats(State(state): State<AppState>) -> Json<BTreeMap<&'static str, u64>> {
+    Json(
+        state
+            .dropped_frames
+            .iter()
+            .map(|entry| (*entry.key(), *entry.value()))
+            .collect(),
+    )
+}
+
+/// Gap, duplicate, reorder and reset counts per inbound `(source, kind)`.
+async fn sequence_stats(State(state): State<AppState>) -> Json<Vec<StreamStats>> {
+    Json(state.sequences.stats())
+}
+
+/// `TimeSync` clock offset and round trip, and message latency, per source.
+async fn clock_stats(State(state): State<AppState>) -> Json<Vec<ClockStats>> {
+    Json(state.clocks.stats())
+}
+
+/// Realtime nodes commands can be routed to.
+async fn list_peers(State(state): State<AppState>) -> Json<Vec<Peer>> {
+    Json(state.peers.list())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::state::Envelope;
+    use shared::proto::{Header, SensorBatch, SystemStatus};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    async fn spawn_server(state: AppState) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app_router(state)).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
 
-    // Handle incoming messages from this client
-    let udp_tx = state.udp_tx.clone();
-    let mut recv_task = tokio::spawn(async move {
-        while let Some(Ok(msg)) = receiver.next().await {
-            match msg {
-                Message::Binary(bytes) => {
-                    // Forward binary messages directly to UDP
-                    // We assume the frontend sends valid MessageWrapper bytes
-                    if let Err(e) = udp_tx.send(bytes).await {
-                        error!("Error forwarding WS message to UDP: {}", e);
-                        break;
-                    }
-                }
-                Message::Text(_) => {
-                    // Ignore text messages for now, or handle specific commands
-                }
-                Message::Close(_) => {
-                    break;
-                }
-                _ => {}
+    async fn get(base: &str, path: &str) -> (u16, serde_json::Value) {
+        request(base, "GET", path, "").await
+    }
+
+    async fn post(base: &str, path: &str, body: &str) -> (u16, serde_json::Value) {
+        request(base, "POST", path, body).await
+    }
+
+    /// Minimal HTTP/1.1 client, returning the status code and JSON body;
+    /// other bodies come back as a JSON string.
+    async fn request(base: &str, method: &str, path: &str, body: &str) -> (u16, serde_json::Value) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let addr = base.trim_start_matches("http://");
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            addr,
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        let body = if head.to_ascii_lowercase().contains("transfer-encoding: chunked") {
+            dechunk(body)
+        } else {
+            body.to_string()
+        };
+        (status, serde_json::from_str(&body).unwrap_or_else(|_| body.into()))
+    }
+
+    /// Body of a `Transfer-Encoding: chunked` response.
+    fn dechunk(mut rest: &str) -> String {
+        let mut body = String::new();
+        loop {
+            let (size, after) = rest.split_once("\r\n").unwrap();
+            let size = usize::from_str_radix(size, 16).unwrap();
+            if size == 0 {
+                return body;
             }
+            body.push_str(&after[..size]);
+            rest = &after[size + 2..];
         }
-    });
+    }
 
-    // If any one of the tasks exit, abort the other.
-    tokio::select! {
-        _ = (&mut send_task) => recv_task.abort(),
-        _ = (&mut recv_task) => send_task.abort(),
-    };
-    
-    info!("WebSocket connection closed");
+    fn state() -> AppState {
+        let (udp_tx, _udp_rx) = tokio::sync::mpsc::channel(10);
+        let state = AppState::new(Config::default(), udp_tx);
+        let batch = MessageWrapper::SensorBatch(SensorBatch {
+            header: Some(Header {
+                source: "hub".to_string(),
+                ..Default::default()
+            }),
+            readings: vec![
+                SensorReading {
+                    sensor_id: "temp".to_string(),
+                    scalar: 21.5,
+                    ..Default::default()
+                },
+                SensorReading {
+                    sensor_id: "volt".to_string(),
+                    scalar: 12.0,
+                    ..Default::default()
+                },
+            ],
+        });
+        // Volatile messages are not replayed to WebSocket clients but are
+        // still current state
+        let status = MessageWrapper::SystemStatus(SystemStatus {
+            header: Some(Header {
+                qos: Some(shared::proto::QosProfile {
+                    durability: shared::proto::Durability::Volatile as i32,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            detail: "ok".to_string(),
+            ..Default::default()
+        });
+        for (micros, msg) in [(1_000_000, batch), (2_000_000, status)] {
+            state.latest_values.insert(msg.clone());
+            let at = UNIX_EPOCH + Duration::from_micros(micros);
+            state.history.push(Envelope::received_at(msg, at, None));
+        }
+        state
+    }
+
+    #[tokio::test]
+    async fn test_state_and_sensors() {
+        let base = spawn_server(state()).await;
+
+        let (status, body) = get(&base, "/api/state").await;
+        assert_eq!(status, 200);
+        assert_eq!(body["SensorBatch"].as_array().unwrap().len(), 2);
+        assert_eq!(body["SystemStatus"][0]["detail"], "ok");
+
+        let (_, body) = get(&base, "/api/sensors").await;
+        assert_eq!(body.as_array().unwrap().len(), 2);
+
+        let (status, body) = get(&base, "/api/sensors/temp").await;
+        assert_eq!(status, 200);
+        assert_eq!(body[0]["source"], "hub");
+        assert_eq!(body[0]["reading"]["scalar"], 21.5);
+
+        let (status, body) = get(&base, "/api/sensors/nope").await;
+        assert_eq!(status, 404);
+        assert!(body["error"].as_str().unwrap().contains("nope"));
+    }
+
+    #[tokio::test]
+    async fn test_history_filters() {
+        let base = spawn_server(state()).await;
+
+        let (_, body) = get(&base, "/api/history").await;
+        assert_eq!(body.as_array().unwrap().len(), 2);
+
+        let (_, body) = get(&base, "/api/history?kind=SystemStatus").await;
+        assert_eq!(body[0]["type"], "SystemStatus");
+        assert_eq!(body[0]["meta"]["received_at_us"], 2_000_000);
+
+        let (_, body) = get(&base, "/api/history?since=1970-01-01T00:00:01.5Z").await;
+        assert_eq!(body.as_array().unwrap().len(), 1);
+
+        let (_, body) = get(&base, "/api/history?until=1000000").await;
+        assert_eq!(body[0]["type"], "SensorBatch");
+
+        let (status, _) = get(&base, "/api/history?since=yesterday").await;
+        assert_eq!(status, 400);
+    }
+
+    #[tokio::test]
+    async fn test_command_returns_ack() {
+        let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut config = Config::default();
+        config.udp.realtime_host = peer.local_addr().unwrap().to_string();
+        let (udp_tx, udp_rx) = tokio::sync::mpsc::channel(10);
+        let state = AppState::new(config, udp_tx);
+        tokio::spawn(crate::udp::udp_sender(state.clone(), udp_rx));
+        let base = spawn_server(state.clone()).await;
+
+        let ack_state = state.clone();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 2048];
+            let (len, _) = peer.recv_from(&mut buf).await.unwrap();
+            let msg = MessageWrapper::from_bytes(&buf[..len]).unwrap();
+            let header = msg.header().unwrap();
+            assert_eq!(header.source, "backend");
+            assert!(header.timestamp.is_some());
+            ack_state.deliveries.ack(&shared::proto::Ack {
+                ok: true,
+                message: "moving".to_string(),
+                seq: header.seq,
+            });
+        });
+
+        let (status, body) = post(&base, "/api/commands/actuator", r#"{"actuatorId":"joint_1","position":0.5}"#).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["kind"], "ActuatorCommand");
+        assert_eq!(body["status"], "acked");
+        assert_eq!(body["message"], "moving");
+    }
+
+    #[tokio::test]
+    async fn test_command_acked_over_udp() {
+        // The peer answers like the mock Realtime node: an Ack sent to the
+        // backend's listener, carrying the command's seq
+        let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut config = Config::default();
+        config.udp.realtime_host = peer.local_addr().unwrap().to_string();
+        let (udp_tx, udp_rx) = tokio::sync::mpsc::channel(10);
+        let state = AppState::new(config, udp_tx);
+        tokio::spawn(crate::udp::udp_sender(state.clone(), udp_rx));
+        let port = 5558;
+        tokio::spawn(crate::udp::udp_listener(state.clone(), ([127, 0, 0, 1], port).into()));
+        let base = spawn_server(state).await;
+
+        tokio::spawn(async move {
+            let mut buf = [0u8; 2048];
+            let (len, _) = peer.recv_from(&mut buf).await.unwrap();
+            let msg = MessageWrapper::from_bytes(&buf[..len]).unwrap();
+            let ack = MessageWrapper::Ack(shared::proto::Ack {
+                ok: true,
+                message: "ActuatorCommand accepted".to_string(),
+                seq: msg.header().unwrap().seq,
+            });
+            peer.send_to(&ack.to_bytes().unwrap(), ("127.0.0.1", port)).await.unwrap();
+        });
+
+        let (status, body) = post(&base, "/api/commands/actuator", r#"{"actuatorId":"joint_1","position":0.5}"#).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["status"], "acked");
+        assert_eq!(body["message"], "ActuatorCommand accepted");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_commands_are_rejected() {
+        let base = spawn_server(state()).await;
+
+        let (status, body) = post(&base, "/api/commands/actuator", r#"{"actuatorId":"joint_1"}"#).await;
+        assert_eq!(status, 400);
+        assert!(body["error"].as_str().unwrap().contains("position"));
+
+        let (status, _) = post(&base, "/api/commands/clock", r#"{"timeScale":"fast"}"#).await;
+        assert_eq!(status, 400);
+
+        let (status, _) = post(&base, "/api/commands/tests", "{}").await;
+        assert_eq!(status, 400);
+    }
+
+    #[tokio::test]
+    async fn test_best_effort_command_is_accepted() {
+        let (udp_tx, mut udp_rx) = tokio::sync::mpsc::channel(10);
+        let base = spawn_server(AppState::new(Config::default(), udp_tx)).await;
+
+        let body = r#"{"header":{"qos":{"reliability":"BEST_EFFORT"}},"faultId":"f1"}"#;
+        let (status, reply) = post(&base, "/api/commands/faults", body).await;
+        assert_eq!(status, 202);
+        assert_eq!(reply["status"], "sent");
+
+        let outbound = udp_rx.recv().await.unwrap();
+        assert_eq!(outbound.msg.kind(), MessageKind::FaultInjection);
+        assert_eq!(outbound.msg.header().unwrap().seq, reply["seq"].as_u64().unwrap());
+        assert!(outbound.reply.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_recorder_start_and_stop() {
+        let dir = std::env::temp_dir().join(format!("api-recorder-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut config = Config::default();
+        config.recorder.dir = dir.clone();
+        let (udp_tx, _udp_rx) = tokio::sync::mpsc::channel(10);
+        let state = AppState::new(config, udp_tx);
+        let base = spawn_server(state.clone()).await;
+
+        let (status, _) = post(&base, "/api/recorder/start", r#"{"name":"bench""#).await;
+        assert_eq!(status, 400);
+        let (status, _) = post(&base, "/api/recorder/start", r#"{"name":5}"#).await;
+        assert_eq!(status, 400);
+        assert!(!state.recorder.status().recording);
+
+        let (status, body) = post(&base, "/api/recorder/start", r#"{"name":"bench"}"#).await;
+        assert_eq!(status, 200);
+        assert_eq!(body["recording"], true);
+        let (status, _) = post(&base, "/api/recorder/start", "").await;
+        assert_eq!(status, 409);
+
+        let msg = MessageWrapper::SystemStatus(SystemStatus::default());
+        let frame = msg.to_bytes().unwrap();
+        state.recorder.record(&Envelope::new(msg), &frame);
+        let (_, body) = get(&base, "/api/recorder").await;
+        assert_eq!((body["name"].as_str(), body["recording"].as_bool()), (Some("bench"), Some(true)));
+
+        // Stopping waits for the writer, so the record is counted by then
+        let (status, body) = post(&base, "/api/recorder/stop", "").await;
+        assert_eq!(status, 200);
+        assert_eq!((body["recording"].as_bool(), body["records"].as_u64()), (Some(false), Some(1)));
+        assert!(dir.join("bench/00000.rec").exists());
+        let (status, _) = post(&base, "/api/recorder/stop", "").await;
+        assert_eq!(status, 409);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_control() {
+        let dir = std::env::temp_dir().join(format!("api-replay-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut config = Config::default();
+        config.recorder.dir = dir.clone();
+        let (udp_tx, _udp_rx) = tokio::sync::mpsc::channel(10);
+        let state = AppState::new(config, udp_tx);
+        state.recorder.start(Some("bench"), std::time::SystemTime::now()).unwrap();
+        for t in 1..=3 {
+            let msg = MessageWrapper::SystemStatus(SystemStatus::default());
+            let frame = msg.to_bytes().unwrap();
+            let at = UNIX_EPOCH + Duration::from_secs(t);
+            state.recorder.record(&Envelope::received_at(msg, at, None), &frame);
+        }
+        state.recorder.stop().unwrap();
+        let base = spawn_server(state.clone()).await;
+
+        let (_, body) = get(&base, "/api/recordings").await;
+        assert_eq!(body[0]["name"], "bench");
+        let (_, body) = get(&base, "/api/replay").await;
+        assert!(body.is_null());
+
+        let (status, body) = post(&base, "/api/replay", r#"{"action":"start","name":"bench","paused":true}"#).await;
+        assert_eq!(status, 200);
+        assert_eq!((body["state"].as_str(), body["end_us"].as_i64()), (Some("paused"), Some(3_000_000)));
+        let (status, body) = post(&base, "/api/replay", r#"{"action":"speed","factor":4}"#).await;
+        assert_eq!((status, body["speed"].as_f64()), (200, Some(4.0)));
+        let (status, _) = post(&base, "/api/replay", r#"{"action":"start","name":"nope"}"#).await;
+        assert_eq!(status, 404);
+        let (status, _) = post(&base, "/api/replay", r#"{"action":"rewind"}"#).await;
+        assert_eq!(status, 400);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export_recording() {
+        let cached = state().latest_values.latest();
+        let dir = std::env::temp_dir().join(format!("api-export-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut config = Config::default();
+        config.recorder.dir = dir.clone();
+        let (udp_tx, _udp_rx) = tokio::sync::mpsc::channel(10);
+        let state = AppState::new(config, udp_tx);
+        state.recorder.start(Some("bench"), std::time::SystemTime::now()).unwrap();
+        for msg in cached {
+            let frame = msg.to_bytes().unwrap();
+            let at = UNIX_EPOCH + Duration::from_secs(1);
+            state.recorder.record(&Envelope::received_at(msg, at, None), &frame);
+        }
+        state.recorder.stop().unwrap();
+        let base = spawn_server(state).await;
+
+        let (status, body) = get(&base, "/api/recordings/bench/export?sensors=volt,x*&since=1970-01-01T00:00:00Z").await;
+        assert_eq!(status, 200);
+        assert_eq!(body, "time,source,volt\n1970-01-01T00:00:01.000000Z,hub,12\n");
+        let (status, _) = get(&base, "/api/recordings/nope/export").await;
+        assert_eq!(status, 404);
+        let (status, body) = get(&base, "/api/recordings/bench/export?format=xlsx").await;
+        assert_eq!(status, 400);
+        assert!(body["error"].as_str().unwrap().contains("xlsx"), "{}", body);
+        let (status, _) = get(&base, "/api/recordings/bench/export?until=later").await;
+        assert_eq!(status, 400);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,252 @@
+use dashmap::DashMap;
+use shared::proto::{Durability, SensorBatch};
+use shared::{MessageKind, MessageWrapper};
+use std::collections::VecDeque;
+
+/// Cache slot: message kind, `Header.source`, and the sensor, actuator, node,
+/// fault or test id the message is about (empty for node-wide messages).
+pub type CacheKey = (MessageKind, String, String);
+
+/// Messages replayed to WebSocket clients when they connect or resubscribe.
+///
+/// Sensor batches are split so every sensor keeps its own latest reading,
+/// whichever batch it arrived in. Retention per slot follows `Header.qos`:
+/// `TRANSIENT_LOCAL` keeps the last `depth` messages (or up to
+/// `keep_all_limit` with `history_keep_all`), `VOLATILE` is never retained,
+/// and messages without a durability keep only the latest.
+///
+/// The newest message of every slot is also kept whatever its durability,
+/// for REST clients asking for current state.
+pub struct LatestCache {
+    slots: DashMap<CacheKey, VecDeque<MessageWrapper>>,
+    newest: DashMap<CacheKey, MessageWrapper>,
+    keep_all_limit: usize,
+}
+
+impl LatestCache {
+    pub fn new(keep_all_limit: usize) -> Self {
+        Self {
+            slots: DashMap::new(),
+            newest: DashMap::new(),
+            keep_all_limit,
+        }
+    }
+
+    pub fn insert(&self, msg: MessageWrapper) {
+        let depth = self.retention(&msg);
+        let kind = msg.kind();
+        let source = msg.source().to_string();
+        for (item, msg) in split(msg) {
+            let key = (kind, source.clone(), item);
+            self.newest.insert(key.clone(), msg.clone());
+            let Some(depth) = depth else {
+                continue;
+            };
+            let mut slot = self.slots.entry(key).or_default();
+            slot.push_back(msg);
+            while slot.len() > depth {
+                slot.pop_front();
+            }
+        }
+    }
+
+    /// Retained messages ordered by slot, oldest first within a slot.
+    pub fn snapshot(&self) -> Vec<MessageWrapper> {
+        let mut slots: Vec<(CacheKey, Vec<MessageWrapper>)> = self
+            .slots
+            .iter()
+            .map(|slot| (slot.key().clone(), slot.value().iter().cloned().collect()))
+            .collect();
+        slots.sort_by(|(a, _), (b, _)| a.cmp(b));
+        slots.into_iter().flat_map(|(_, msgs)| msgs).collect()
+    }
+
+    /// The newest message of every slot, ordered by slot. Unlike `snapshot`,
+    /// this includes `VOLATILE` messages.
+    pub fn latest(&self) -> Vec<MessageWrapper> {
+        let mut slots: Vec<(CacheKey, MessageWrapper)> = self
+            .newest
+            .iter()
+            .map(|slot| (slot.key().clone(), slot.value().clone()))
+            .collect();
+        slots.sort_by(|(a, _), (b, _)| a.cmp(b));
+        slots.into_iter().map(|(_, msg)| msg).collect()
+    }
+
+    /// How many messages of this kind to retain, or `None` for volatile ones.
+    fn retention(&self, msg: &MessageWrapper) -> Option<usize> {
+        let Some(qos) = msg.qos() else {
+            return Some(1);
+        };
+        match qos.durability() {
+            Durability::Volatile => None,
+            Durability::TransientLocal if qos.history_keep_all => Some(self.keep_all_limit),
+            Durability::TransientLocal => Some((qos.depth as usize).clamp(1, self.keep_all_limit)),
+            Durability::Unspecified => Some(1),
+        }
+    }
+}
+
+/// Breaks a message into its cache items.
+fn split(msg: MessageWrapper) -> Vec<(String, MessageWrapper)> {
+    let item = match &msg {
+        MessageWrapper::SensorBatch(batch) => {
+            return batch
+                .readings
+                .iter()
+                .map(|reading| {
+                    let single = SensorBatch {
+                        header: batch.header.clone(),
+                        readings: vec![reading.clone()],
+                    };
+                    (reading.sensor_id.clone(), MessageWrapper::SensorBatch(single))
+                })
+                .collect();
+        }
+        MessageWrapper::ActuatorCommand(cmd) => cmd.actuator_id.clone(),
+        MessageWrapper::Heartbeat(hb) => hb.node_id.clone(),
+        MessageWrapper::FaultInjection(fault) => fault.fault_id.clone(),
+        MessageWrapper::TestCase(test) => test.test_id.clone(),
+        MessageWrapper::TestResult(result) => result.test_id.clone(),
+        _ => String::new(),
+    };
+    vec![(item, msg)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::proto::{Header, QosProfile, SensorReading, SystemStatus, TestCase};
+
+    fn status(detail: &str, qos: Option<QosProfile>) -> MessageWrapper {
+        MessageWrapper::SystemStatus(SystemStatus {
+            header: Some(Header {
+                qos,
+                ..Default::default()
+            }),
+            detail: detail.to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn transient(depth: u32, keep_all: bool) -> Option<QosProfile> {
+        Some(QosProfile {
+            durability: Durability::TransientLocal as i32,
+            depth,
+            history_keep_all: keep_all,
+            ..Default::default()
+        })
+    }
+
+    fn details(cache: &LatestCache) -> Vec<String> {
+        cache
+            .snapshot()
+            .into_iter()
+            .filter_map(|msg| match msg {
+                MessageWrapper::SystemStatus(s) => Some(s.detail),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_default_keeps_latest_only() {
+        let cache = LatestCache::new(100);
+        cache.insert(status("a", None));
+        cache.insert(status("b", None));
+        assert_eq!(details(&cache), ["b"]);
+    }
+
+    #[test]
+    fn test_transient_local_keeps_depth() {
+        let cache = LatestCache::new(100);
+        for detail in ["a", "b", "c", "d"] {
+            cache.insert(status(detail, transient(3, false)));
+        }
+        assert_eq!(details(&cache), ["b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_latest_is_newest_per_slot() {
+        let cache = LatestCache::new(100);
+        for detail in ["a", "b", "c"] {
+            cache.insert(status(detail, transient(3, false)));
+        }
+        let latest = cache.latest();
+        assert_eq!(latest.len(), 1);
+        assert!(matches!(&latest[0], MessageWrapper::SystemStatus(s) if s.detail == "c"));
+    }
+
+    #[test]
+    fn test_keep_all_is_bounded() {
+        let cache = LatestCache::new(2);
+        for detail in ["a", "b", "c"] {
+            cache.insert(status(detail, transient(0, true)));
+        }
+        assert_eq!(details(&cache), ["b", "c"]);
+    }
+
+    #[test]
+    fn test_volatile_is_never_retained() {
+        let cache = LatestCache::new(100);
+        let volatile = Some(QosProfile {
+            durability: Durability::Volatile as i32,
+            ..Default::default()
+        });
+        cache.insert(status("a", volatile));
+        assert!(cache.snapshot().is_empty());
+        // Still reported as current state
+        assert_eq!(cache.latest().len(), 1);
+
+        cache.insert(MessageWrapper::TestCase(TestCase::default()));
+        assert_eq!(cache.snapshot().len(), 1);
+    }
+
+    fn batch(source: &str, sensors: &[(&str, f64)]) -> MessageWrapper {
+        MessageWrapper::SensorBatch(SensorBatch {
+            header: Some(Header {
+                source: source.to_string(),
+                ..Default::default()
+            }),
+            readings: sensors
+                .iter()
+                .map(|(id, scalar)| SensorReading {
+                    sensor_id: id.to_string(),
+                    scalar: *scalar,
+                    ..Default::default()
+                })
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn test_sensors_are_kept_per_source_and_id() {
+        let cache = LatestCache::new(100);
+        cache.insert(batch("hub", &[("temp", 1.0), ("volt", 2.0)]));
+        cache.insert(batch("motion", &[("temp", 3.0)]));
+        cache.insert(batch("hub", &[("temp", 4.0)]));
+
+        let readings: Vec<(String, String, f64)> = cache
+            .snapshot()
+            .into_iter()
+            .flat_map(|msg| match msg {
+                MessageWrapper::SensorBatch(b) => {
+                    let source = b.header.unwrap_or_default().source;
+                    b.readings
+                        .into_iter()
+                        .map(|r| (source.clone(), r.sensor_id, r.scalar))
+                        .collect::<Vec<_>>()
+                }
+                _ => vec![],
+            })
+            .collect();
+        assert_eq!(
+            readings,
+            [
+                ("hub".to_string(), "temp".to_string(), 4.0),
+                ("hub".to_string(), "volt".to_string(), 2.0),
+                ("motion".to_string(), "temp".to_string(), 3.0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,596 @@
+//! Raw capture of every UDP datagram to and from the Realtime side.
+//!
+//! Datagrams are written to a classic pcap file with link type
+//! `LINKTYPE_RAW`: each one is wrapped in an IPv4 (or IPv6, when either end
+//! is IPv6) and UDP header carrying the real addresses, ports and checksums,
+//! so Wireshark and `tcpreplay` read it like a capture taken on the wire.
+//! Undecodable datagrams are captured too, as they arrived.
+//!
+//! Packets are built and written on a dedicated thread fed through a bounded
+//! queue, so a slow disk never holds up the UDP tasks; datagrams arriving
+//! while the queue is full are counted and left out of the capture.
+//!
+//! The `import` subcommand turns a capture into a recording: the decodable
+//! datagrams sent to `udp.listen` become records timed by when they were
+//! captured, which replay and export then read like any other recording.
+//! Latency estimates are not captured, so imported records have none.
+
+use crate::config::{CaptureConfig, Config, RecorderConfig};
+use crate::recorder::{self, Record, RecorderError, RecorderStatus};
+use clap::Args;
+use shared::MessageWrapper;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{error, info, warn};
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+/// Magic of pcap files with nanosecond timestamps.
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const LINKTYPE_RAW: u32 = 101;
+const SNAPLEN: u32 = 65535;
+const IPPROTO_UDP: u8 = 17;
+const TTL: u8 = 64;
+/// Largest packet accepted when reading, the snaplen tools use by default.
+const MAX_PACKET_LEN: usize = 262_144;
+
+/// Appends datagrams to a pcap file; stops at the first write error.
+pub struct Capture {
+    queue: SyncSender<Command>,
+    /// Datagrams left out because the queue was full, since the last report.
+    dropped: Arc<AtomicU64>,
+}
+
+enum Command {
+    Datagram {
+        src: SocketAddr,
+        dst: SocketAddr,
+        payload: Vec<u8>,
+        at: SystemTime,
+    },
+    #[cfg(test)]
+    Flush(mpsc::Sender<()>),
+}
+
+impl Capture {
+    /// Creates `path`, replacing any previous capture there, and starts the
+    /// writer thread.
+    pub fn create(path: &Path, config: &CaptureConfig) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&PCAP_MAGIC.to_le_bytes())?;
+        out.write_all(&2u16.to_le_bytes())?;
+        out.write_all(&4u16.to_le_bytes())?;
+        out.write_all(&0i32.to_le_bytes())?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(&SNAPLEN.to_le_bytes())?;
+        out.write_all(&LINKTYPE_RAW.to_le_bytes())?;
+        let (queue, rx) = mpsc::sync_channel(config.queue_capacity);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let writer = Writer {
+            out,
+            locals: HashMap::new(),
+            dropped: dropped.clone(),
+            flush_interval: Duration::from_millis(config.flush_interval_ms),
+        };
+        std::thread::Builder::new()
+            .name("capture".to_string())
+            .spawn(move || writer.run(rx))?;
+        info!("Capturing UDP traffic to {}", path.display());
+        Ok(Self { queue, dropped })
+    }
+
+    /// Captures a datagram sent from `src` to `dst` at `at`. The local end
+    /// may be the socket's wildcard bind address; it is replaced with the
+    /// address the host routes to the other end from.
+    pub fn record(&self, src: SocketAddr, dst: SocketAddr, payload: &[u8], at: SystemTime) {
+        let datagram = Command::Datagram {
+            src,
+            dst,
+            payload: payload.to_vec(),
+            at,
+        };
+        if let Err(TrySendError::Full(_)) = self.queue.try_send(datagram) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Waits until everything captured so far is on disk.
+    #[cfg(test)]
+    pub fn flush(&self) {
+        let (done, wait) = mpsc::channel();
+        if self.queue.send(Command::Flush(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+}
+
+/// The capture thread's side of the queue.
+struct Writer {
+    out: BufWriter<File>,
+    /// Local address used to reach each peer, for wildcard-bound sockets.
+    locals: HashMap<IpAddr, IpAddr>,
+    dropped: Arc<AtomicU64>,
+    flush_interval: Duration,
+}
+
+impl Writer {
+    fn run(mut self, rx: Receiver<Command>) {
+        let mut last_flush = Instant::now();
+        loop {
+            let timeout = self.flush_interval.saturating_sub(last_flush.elapsed());
+            let result = match rx.recv_timeout(timeout) {
+                Ok(Command::Datagram { src, dst, payload, at }) => self.write(src, dst, &payload, at),
+                #[cfg(test)]
+                Ok(Command::Flush(done)) => {
+                    let result = self.out.flush();
+                    let _ = done.send(());
+                    result
+                }
+                Err(RecvTimeoutError::Timeout) => Ok(()),
+                Err(RecvTimeoutError::Disconnected) => {
+                    let _ = self.out.flush();
+                    return;
+                }
+            };
+            let result = result.and_then(|()| {
+                if last_flush.elapsed() < self.flush_interval {
+                    return Ok(());
+                }
+                last_flush = Instant::now();
+                let dropped = self.dropped.swap(0, Ordering::Relaxed);
+                if dropped > 0 {
+                    warn!("Capture queue full, {} datagrams not captured", dropped);
+                }
+                self.out.flush()
+            });
+            if let Err(e) = result {
+                // Dropping the receiver makes later datagrams fail to queue
+                error!("Stopped UDP capture: {}", e);
+                return;
+            }
+        }
+    }
+
+    fn write(&mut self, src: SocketAddr, dst: SocketAddr, payload: &[u8], at: SystemTime) -> io::Result<()> {
+        let (src, dst) = (self.resolve(src, dst), self.resolve(dst, src));
+        let Some(packet) = ip_packet(src, dst, payload) else {
+            warn!("Not capturing {}-byte datagram from {} to {}: too large for one IP packet", payload.len(), src, dst);
+            return Ok(());
+        };
+        let since_epoch = at.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let out = &mut self.out;
+        out.write_all(&(since_epoch.as_secs() as u32).to_le_bytes())?;
+        out.write_all(&since_epoch.subsec_micros().to_le_bytes())?;
+        let len = packet.len() as u32;
+        out.write_all(&len.min(SNAPLEN).to_le_bytes())?;
+        out.write_all(&len.to_le_bytes())?;
+        out.write_all(&packet[..packet.len().min(SNAPLEN as usize)])
+    }
+
+    /// `local`, with a wildcard address replaced by the one the host sends
+    /// to `peer` from. Without per-packet destination info this is the best
+    /// guess for inbound datagrams too.
+    fn resolve(&mut self, local: SocketAddr, peer: SocketAddr) -> SocketAddr {
+        if !local.ip().is_unspecified() {
+            return local;
+        }
+        let ip = *self
+            .locals
+            .entry(peer.ip())
+            .or_insert_with(|| route_source(peer).unwrap_or(local.ip()));
+        SocketAddr::new(ip, local.port())
+    }
+}
+
+/// Source address of the route to `peer`, found by connecting a probe
+/// socket; connecting a UDP socket sends nothing.
+fn route_source(peer: SocketAddr) -> io::Result<IpAddr> {
+    let bind: SocketAddr = match peer {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let probe = UdpSocket::bind(bind)?;
+    probe.connect(peer)?;
+    Ok(probe.local_addr()?.ip())
+}
+
+/// `payload` as a UDP datagram in an IPv4 packet, or IPv6 when either
+/// address is IPv6 (IPv4 ones are then mapped). `None` when the datagram
+/// is too large for the 16-bit length fields.
+fn ip_packet(src: SocketAddr, dst: SocketAddr, payload: &[u8]) -> Option<Vec<u8>> {
+    let udp_len = u16::try_from(8 + payload.len()).ok()?;
+    let mut packet = Vec::with_capacity(48 + payload.len());
+    let pseudo = match (src.ip(), dst.ip()) {
+        (IpAddr::V4(s), IpAddr::V4(d)) => {
+            let total_len = 20u16.checked_add(udp_len)?;
+            packet.extend_from_slice(&[0x45, 0]);
+            packet.extend_from_slice(&total_len.to_be_bytes());
+            packet.extend_from_slice(&[0, 0, 0x40, 0, TTL, IPPROTO_UDP, 0, 0]);
+            packet.extend_from_slice(&s.octets());
+            packet.extend_from_slice(&d.octets());
+            let header_sum = !fold(sum(&packet));
+            packet[10..12].copy_from_slice(&header_sum.to_be_bytes());
+            sum(&s.octets()) + sum(&d.octets()) + u32::from(IPPROTO_UDP) + u32::from(udp_len)
+        }
+        (s, d) => {
+            let (s, d) = (to_v6(s), to_v6(d));
+            packet.extend_from_slice(&[0x60, 0, 0, 0]);
+            packet.extend_from_slice(&udp_len.to_be_bytes());
+            packet.extend_from_slice(&[IPPROTO_UDP, TTL]);
+            packet.extend_from_slice(&s.octets());
+            packet.extend_from_slice(&d.octets());
+            sum(&s.octets()) + sum(&d.octets()) + u32::from(IPPROTO_UDP) + u32::from(udp_len)
+        }
+    };
+
+    let udp_start = packet.len();
+    packet.extend_from_slice(&src.port().to_be_bytes());
+    packet.extend_from_slice(&dst.port().to_be_bytes());
+    packet.extend_from_slice(&udp_len.to_be_bytes());
+    packet.extend_from_slice(&[0, 0]);
+    packet.extend_from_slice(payload);
+    // An all-zero checksum means "none" in UDP, so it is sent as all ones
+    let checksum = match !fold(pseudo + sum(&packet[udp_start..])) {
+        0 => 0xffff,
+        checksum => checksum,
+    };
+    packet[udp_start + 6..udp_start + 8].copy_from_slice(&checksum.to_be_bytes());
+    Some(packet)
+}
+
+fn to_v6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+/// Sum of big-endian 16-bit words, the last one zero padded.
+pub fn sum(bytes: &[u8]) -> u32 {
+    bytes
+        .chunks(2)
+        .map(|word| u32::from(u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)])))
+        .sum()
+}
+
+/// Folds carries back in for the one's complement sum.
+pub fn fold(mut sum: u32) -> u16 {
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+/// A UDP datagram read back from a pcap file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet {
+    pub at_us: i64,
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    pub payload: Vec<u8>,
+}
+
+/// Reads UDP datagrams from a `LINKTYPE_RAW` pcap file such as the ones
+/// [`Capture`] writes. Packets that are not whole, unfragmented UDP
+/// datagrams are skipped.
+pub struct PcapReader<R> {
+    input: R,
+    big_endian: bool,
+    nanos: bool,
+}
+
+impl<R: Read> PcapReader<R> {
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut header = [0u8; 24];
+        input.read_exact(&mut header)?;
+        let (big_endian, nanos) = match u32::from_le_bytes([header[0], header[1], header[2], header[3]]) {
+            PCAP_MAGIC => (false, false),
+            PCAP_MAGIC_NANOS => (false, true),
+            magic if magic == PCAP_MAGIC.swap_bytes() => (true, false),
+            magic if magic == PCAP_MAGIC_NANOS.swap_bytes() => (true, true),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "not a pcap file")),
+        };
+        let reader = Self {
+            input,
+            big_endian,
+            nanos,
+        };
+        // The upper bits of the link type field carry FCS flags
+        let link_type = reader.u32(&header[20..24]) & 0xffff;
+        if link_type != LINKTYPE_RAW {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported pcap link type {}, expected raw IP ({})", link_type, LINKTYPE_RAW),
+            ));
+        }
+        Ok(reader)
+    }
+
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+
+    /// The next UDP datagram, or `None` at the end of the file. A file cut
+    /// off mid-packet, like a capture still being written, ends there.
+    pub fn next_packet(&mut self) -> io::Result<Option<Packet>> {
+        loop {
+            let mut header = [0u8; 16];
+            if !read_or_eof(&mut self.input, &mut header)? {
+                return Ok(None);
+            }
+            let incl_len = self.u32(&header[8..12]) as usize;
+            if incl_len > MAX_PACKET_LEN {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("pcap packet of {} bytes is larger than {}", incl_len, MAX_PACKET_LEN),
+                ));
+            }
+            let mut packet = vec![0; incl_len];
+            if !read_or_eof(&mut self.input, &mut packet)? {
+                return Ok(None);
+            }
+            // Cut short by the snaplen
+            if incl_len != self.u32(&header[12..16]) as usize {
+                continue;
+            }
+            let Some((src, dst, payload)) = udp_datagram(&packet) else {
+                continue;
+            };
+            let fraction = i64::from(self.u32(&header[4..8]));
+            let micros = if self.nanos { fraction / 1000 } else { fraction };
+            let at_us = i64::from(self.u32(&header[..4])) * 1_000_000 + micros;
+            return Ok(Some(Packet {
+                at_us,
+                src,
+                dst,
+                payload: payload.to_vec(),
+            }));
+        }
+    }
+}
+
+/// Fills `buf`, or returns `false` if the input ends first.
+fn read_or_eof(input: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    match input.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// The addresses and payload of the UDP datagram in an IP packet. IPv6
+/// extension headers are not followed and IPv4-mapped addresses are
+/// unmapped, undoing what [`ip_packet`] does for mixed families.
+fn udp_datagram(packet: &[u8]) -> Option<(SocketAddr, SocketAddr, &[u8])> {
+    let word = |at: usize| packet.get(at..at + 2).map(|b| usize::from(u16::from_be_bytes([b[0], b[1]])));
+    let (src, dst, udp) = match packet.first()? >> 4 {
+        4 => {
+            let header_len = usize::from(packet[0] & 0x0f) * 4;
+            // More fragments flag and fragment offset
+            let fragmented = word(6)? & 0x3fff != 0;
+            if header_len < 20 || *packet.get(9)? != IPPROTO_UDP || fragmented {
+                return None;
+            }
+            let src: [u8; 4] = packet.get(12..16)?.try_into().ok()?;
+            let dst: [u8; 4] = packet.get(16..20)?.try_into().ok()?;
+            (IpAddr::from(src), IpAddr::from(dst), packet.get(header_len..word(2)?)?)
+        }
+        6 => {
+            if *packet.get(6)? != IPPROTO_UDP {
+                return None;
+            }
+            let src: [u8; 16] = packet.get(8..24)?.try_into().ok()?;
+            let dst: [u8; 16] = packet.get(24..40)?.try_into().ok()?;
+            let udp = packet.get(40..40 + word(4)?)?;
+            (Ipv6Addr::from(src).to_canonical(), Ipv6Addr::from(dst).to_canonical(), udp)
+        }
+        _ => return None,
+    };
+    let port = |at: usize| udp.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+    let udp_len = usize::from(port(4)?);
+    if udp_len < 8 {
+        return None;
+    }
+    let payload = udp.get(8..udp_len)?;
+    Some((SocketAddr::new(src, port(0)?), SocketAddr::new(dst, port(2)?), payload))
+}
+
+/// Writes the decodable datagrams in the capture at `path` that were sent to
+/// `listen` as a new recording called `name`. A wildcard `listen` address
+/// matches any destination address on its port.
+pub fn import(path: &Path, listen: SocketAddr, config: &RecorderConfig, name: &str) -> Result<RecorderStatus, RecorderError> {
+    let mut reader = PcapReader::new(BufReader::new(File::open(path)?))?;
+    let inbound = |packet: &Packet| {
+        packet.dst.port() == listen.port() && (listen.ip().is_unspecified() || packet.dst.ip() == listen.ip())
+    };
+    let records = std::iter::from_fn(|| reader.next_packet().transpose()).filter_map(|packet| match packet {
+        Ok(packet) if !inbound(&packet) || MessageWrapper::from_bytes(&packet.payload).is_err() => None,
+        Ok(packet) => Some(Ok(Record {
+            received_at_us: packet.at_us,
+            latency_us: None,
+            frame: packet.payload,
+        })),
+        Err(e) => Some(Err(e)),
+    });
+    recorder::import(config, name, records)
+}
+
+/// `import` subcommand: turns a capture into a recording.
+#[derive(Debug, Args)]
+pub struct ImportArgs {
+    /// pcap file written by the backend's capture
+    pub capture: PathBuf,
+
+    /// Name of the new recording under `recorder.dir`
+    pub name: String,
+}
+
+/// Runs the `import` subcommand, returning the process exit code.
+pub fn run(config: &Config, args: &ImportArgs) -> i32 {
+    match import(&args.capture, config.udp.listen, &config.recorder, &args.name) {
+        Ok(status) => {
+            println!("Imported {} records into recording {}", status.records, args.name);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ipv4_headers_and_checksums() {
+        let src: SocketAddr = "10.0.0.2:5001".parse().unwrap();
+        let dst: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+        let packet = ip_packet(src, dst, b"hello").unwrap();
+        assert_eq!(packet.len(), 20 + 8 + 5);
+        assert_eq!(&packet[2..4], &33u16.to_be_bytes());
+        assert_eq!(packet[9], IPPROTO_UDP);
+        // A valid checksum makes the covered bytes sum to all ones
+        assert_eq!(fold(sum(&packet[..20])), 0xffff);
+        let pseudo = sum(&packet[12..20]) + u32::from(IPPROTO_UDP) + 13;
+        assert_eq!(fold(pseudo + sum(&packet[20..])), 0xffff);
+        assert_eq!(&packet[20..22], &5001u16.to_be_bytes());
+        assert_eq!(&packet[28..], b"hello");
+    }
+
+    #[test]
+    fn test_mixed_families_use_ipv6() {
+        let src: SocketAddr = "[::1]:6000".parse().unwrap();
+        let dst: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let packet = ip_packet(src, dst, b"abc").unwrap();
+        assert_eq!(packet.len(), 40 + 8 + 3);
+        assert_eq!(packet[0] >> 4, 6);
+        assert_eq!(&packet[24..40], &Ipv6Addr::from([0, 0, 0, 0, 0, 0xffff, 0x7f00, 1]).octets());
+        let pseudo = sum(&packet[8..40]) + u32::from(IPPROTO_UDP) + 11;
+        assert_eq!(fold(pseudo + sum(&packet[40..])), 0xffff);
+    }
+
+    #[test]
+    fn test_pcap_file_layout() {
+        let path = std::env::temp_dir().join(format!("capture-{}.pcap", std::process::id()));
+        let capture = Capture::create(&path, &CaptureConfig::default()).unwrap();
+        let at = UNIX_EPOCH + Duration::from_micros(1_700_000_000_250_000);
+        capture.record("127.0.0.1:1".parse().unwrap(), "127.0.0.1:2".parse().unwrap(), &[0xff; 3], at);
+        capture.flush();
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[..4], &[0xd4, 0xc3, 0xb2, 0xa1]);
+        assert_eq!(&bytes[20..24], &LINKTYPE_RAW.to_le_bytes());
+        let record = &bytes[24..];
+        assert_eq!(&record[..4], &1_700_000_000u32.to_le_bytes());
+        assert_eq!(&record[4..8], &250_000u32.to_le_bytes());
+        assert_eq!(&record[8..12], &31u32.to_le_bytes());
+        assert_eq!(record.len(), 16 + 31);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_oversized_datagrams_are_skipped() {
+        let src: SocketAddr = "10.0.0.2:5001".parse().unwrap();
+        let dst: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+        assert!(ip_packet(src, dst, &[0; 65_507]).is_some());
+        assert!(ip_packet(src, dst, &[0; 65_508]).is_none());
+        let v6: SocketAddr = "[::1]:5000".parse().unwrap();
+        assert!(ip_packet(src, v6, &[0; 65_527]).is_some());
+        assert!(ip_packet(src, v6, &[0; 65_528]).is_none());
+    }
+
+    #[test]
+    fn test_wildcard_local_address_is_resolved() {
+        let path = std::env::temp_dir().join(format!("capture-wildcard-{}.pcap", std::process::id()));
+        let capture = Capture::create(&path, &CaptureConfig::default()).unwrap();
+        let peer: SocketAddr = "127.0.0.1:6000".parse().unwrap();
+        capture.record(peer, "0.0.0.0:5000".parse().unwrap(), b"in", SystemTime::now());
+        capture.record("0.0.0.0:4000".parse().unwrap(), peer, b"out", SystemTime::now());
+        capture.flush();
+
+        let bytes = std::fs::read(&path).unwrap();
+        let inbound = &bytes[24 + 16..24 + 16 + 30];
+        assert_eq!(&inbound[16..20], &[127, 0, 0, 1]);
+        assert_eq!(&inbound[22..24], &5000u16.to_be_bytes());
+        let pseudo = sum(&inbound[12..20]) + u32::from(IPPROTO_UDP) + 10;
+        assert_eq!(fold(pseudo + sum(&inbound[20..])), 0xffff);
+        let outbound = &bytes[24 + 16 + 30 + 16..];
+        assert_eq!(&outbound[12..16], &[127, 0, 0, 1]);
+        assert_eq!(&outbound[20..22], &4000u16.to_be_bytes());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_import_keeps_decodable_inbound_datagrams() {
+        use crate::recorder::RecordingReader;
+        use shared::proto::{Header, Heartbeat};
+
+        let path = std::env::temp_dir().join(format!("capture-import-{}.pcap", std::process::id()));
+        let dir = std::env::temp_dir().join(format!("capture-import-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let heartbeat = |seq| {
+            MessageWrapper::Heartbeat(Heartbeat {
+                header: Some(Header {
+                    seq,
+                    ..Default::default()
+                }),
+                node_id: "rt".to_string(),
+                ..Default::default()
+            })
+            .to_bytes()
+            .unwrap()
+        };
+        let at = |us: u64| UNIX_EPOCH + Duration::from_micros(1_700_000_000_000_000 + us);
+        let listen: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+        let peer: SocketAddr = "127.0.0.1:6000".parse().unwrap();
+        let capture = Capture::create(&path, &CaptureConfig::default()).unwrap();
+        capture.record(peer, listen, &heartbeat(1), at(0));
+        capture.record("127.0.0.1:4000".parse().unwrap(), peer, &heartbeat(2), at(100));
+        capture.record(peer, listen, b"not a frame", at(200));
+        capture.record("[::1]:6000".parse().unwrap(), listen, &heartbeat(3), at(300));
+        capture.flush();
+
+        let config = RecorderConfig {
+            dir: dir.clone(),
+            ..Default::default()
+        };
+        let wildcard: SocketAddr = "0.0.0.0:5000".parse().unwrap();
+        let status = import(&path, wildcard, &config, "imported").unwrap();
+        assert_eq!(status.records, 2);
+        let mut reader = RecordingReader::open(&dir.join("imported")).unwrap();
+        let mut records = Vec::new();
+        while let Some(record) = reader.next_record().unwrap() {
+            records.push(record);
+        }
+        let expected = [(0, heartbeat(1)), (300, heartbeat(3))];
+        assert_eq!(records.len(), expected.len());
+        for (record, (us, frame)) in records.iter().zip(expected) {
+            assert_eq!(record.received_at_us, 1_700_000_000_000_000 + us as i64);
+            assert_eq!(record.latency_us, None);
+            assert_eq!(record.frame, frame);
+        }
+
+        assert!(matches!(
+            import(&path, wildcard, &config, "imported"),
+            Err(RecorderError::Exists(_))
+        ));
+        std::fs::write(&path, b"not a capture at all").unwrap();
+        assert!(matches!(import(&path, wildcard, &config, "garbage"), Err(RecorderError::Io(_))));
+        assert!(!dir.join("garbage").exists());
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
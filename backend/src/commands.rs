@@ -0,0 +1,106 @@
+//! `POST /api/commands/*`: send commands to the Realtime side over HTTP.
+//!
+//! Bodies use the proto3 JSON mapping of the command message. The backend
+//! fills in `Header.source`, `seq` and `timestamp`, keeps `dest`, `frame_id`
+//! and `qos`, and sends the command `RELIABLE` unless the body asks for
+//! `BEST_EFFORT`. Reliable commands answer with their `Ack` or a timeout.
+
+use crate::api::ApiError;
+use crate::reliable::{is_reliable, Delivery};
+use crate::state::AppState;
+use crate::timesync::{self, BACKEND_SOURCE};
+use crate::udp::Outbound;
+use crate::validate;
+use axum::{
+    extract::{rejection::JsonRejection, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Serialize;
+use shared::proto::{ActuatorCommand, ClockModulation, FaultInjection, QosProfile, Reliability, TestCase};
+use shared::ws::DeliveryStatus;
+use shared::{MessageKind, MessageWrapper};
+use std::time::SystemTime;
+use tokio::sync::oneshot;
+use tracing::info;
+
+/// Response to a command that was handed to the UDP sender.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum CommandReply {
+    /// Outcome of a `RELIABLE` command.
+    Delivery(Delivery),
+    /// A `BEST_EFFORT` command was sent; nothing more is known.
+    Sent { kind: MessageKind, seq: u64, status: &'static str },
+}
+
+type CommandResult = Result<(StatusCode, Json<CommandReply>), ApiError>;
+
+pub async fn actuator(State(state): State<AppState>, body: Result<Json<ActuatorCommand>, JsonRejection>) -> CommandResult {
+    let Json(cmd) = body.map_err(|e| ApiError::bad_request(e.body_text()))?;
+    send(&state, MessageWrapper::ActuatorCommand(cmd)).await
+}
+
+pub async fn clock(State(state): State<AppState>, body: Result<Json<ClockModulation>, JsonRejection>) -> CommandResult {
+    let Json(clock) = body.map_err(|e| ApiError::bad_request(e.body_text()))?;
+    send(&state, MessageWrapper::ClockModulation(clock)).await
+}
+
+pub async fn fault(State(state): State<AppState>, body: Result<Json<FaultInjection>, JsonRejection>) -> CommandResult {
+    let Json(fault) = body.map_err(|e| ApiError::bad_request(e.body_text()))?;
+    send(&state, MessageWrapper::FaultInjection(fault)).await
+}
+
+pub async fn test(State(state): State<AppState>, body: Result<Json<TestCase>, JsonRejection>) -> CommandResult {
+    let Json(test) = body.map_err(|e| ApiError::bad_request(e.body_text()))?;
+    send(&state, MessageWrapper::TestCase(test)).await
+}
+
+/// Checks the command, fills in the header, queues it and waits for its
+/// outcome.
+async fn send(state: &AppState, mut msg: MessageWrapper) -> CommandResult {
+    validate::check_command(&msg, &state.config.commands).map_err(ApiError::bad_request)?;
+    let kind = msg.kind();
+    let header = msg.header_mut().expect("commands carry a header");
+    header.source = BACKEND_SOURCE.to_string();
+    header.timestamp = Some(timesync::to_timestamp(SystemTime::now()));
+    let qos = header.qos.get_or_insert_with(QosProfile::default);
+    if qos.reliability() == Reliability::Unspecified {
+        qos.set_reliability(Reliability::Reliable);
+    }
+
+    let mut outbound = Outbound::new(msg);
+    if !is_reliable(&outbound.msg) {
+        let seq = state.deliveries.next_seq();
+        if let Some(header) = outbound.msg.header_mut() {
+            header.seq = seq;
+        }
+        queue(state, outbound).await?;
+        info!("Sent {} seq {} (best effort)", kind, seq);
+        let reply = CommandReply::Sent { kind, seq, status: "sent" };
+        return Ok((StatusCode::ACCEPTED, Json(reply)));
+    }
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    outbound.reply = Some(reply_tx);
+    queue(state, outbound).await?;
+    let delivery = reply_rx
+        .await
+        .map_err(|_| ApiError::unavailable("UDP sender stopped"))?;
+    info!("{} seq {} finished: {:?}", kind, delivery.seq, delivery.status);
+    let status = match delivery.status {
+        DeliveryStatus::Acked { .. } => StatusCode::OK,
+        DeliveryStatus::Rejected { .. } => StatusCode::CONFLICT,
+        DeliveryStatus::TimedOut => StatusCode::GATEWAY_TIMEOUT,
+        DeliveryStatus::Unroutable { .. } => StatusCode::BAD_GATEWAY,
+    };
+    Ok((status, Json(CommandReply::Delivery(delivery))))
+}
+
+async fn queue(state: &AppState, outbound: Outbound) -> Result<(), ApiError> {
+    state
+        .udp_tx
+        .send(outbound)
+        .await
+        .map_err(|_| ApiError::unavailable("UDP sender stopped"))
+}
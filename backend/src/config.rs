@@ -0,0 +1,728 @@
+//! Backend configuration.
+//!
+//! Values are layered: built-in defaults, then an optional TOML file, then
+//! environment variables and command line flags (flags win over env).
+
+use crate::capture::ImportArgs;
+use crate::export::ExportArgs;
+use crate::peers::BROADCAST_DEST;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to parse config file {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("Invalid configuration: {0}")]
+    Invalid(String),
+}
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Simulation backend bridging the Realtime UDP link to WebSocket clients")]
+pub struct Cli {
+    /// TOML configuration file
+    #[arg(short, long, env = "OPER_CONFIG", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Print the resolved configuration as TOML and exit
+    #[arg(long)]
+    pub print_config: bool,
+
+    #[command(flatten)]
+    pub overrides: Overrides,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Export recorded sensor data as CSV or Parquet and exit
+    Export(ExportArgs),
+    /// Convert a pcap capture into a recording for replay and export, and exit
+    Import(ImportArgs),
+}
+
+/// Settings that can be overridden from the command line or environment.
+#[derive(Debug, Default, Args)]
+pub struct Overrides {
+    /// HTTP/WebSocket bind address
+    #[arg(long, env = "OPER_HTTP_BIND")]
+    pub http_bind: Option<SocketAddr>,
+
+    /// UDP address to receive Realtime traffic on
+    #[arg(long, env = "OPER_UDP_LISTEN")]
+    pub udp_listen: Option<SocketAddr>,
+
+    /// Realtime node to send commands to (host:port)
+    #[arg(long, env = "REALTIME_HOST")]
+    pub realtime_host: Option<String>,
+
+    /// Capacity of the broadcast channel feeding WebSocket clients
+    #[arg(long, env = "OPER_BROADCAST_CAPACITY")]
+    pub broadcast_capacity: Option<usize>,
+
+    /// Capacity of the outbound UDP queue
+    #[arg(long, env = "OPER_UDP_OUTBOUND_CAPACITY")]
+    pub udp_outbound_capacity: Option<usize>,
+
+    /// pcap file to capture every UDP datagram to
+    #[arg(long, env = "OPER_CAPTURE")]
+    pub capture: Option<PathBuf>,
+
+    /// Log filter used when RUST_LOG is not set
+    #[arg(long, env = "OPER_LOG_LEVEL")]
+    pub log_level: Option<String>,
+
+    #[arg(long, env = "OPER_LOG_FORMAT", value_enum)]
+    pub log_format: Option<LogFormat>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub http: HttpConfig,
+    pub udp: UdpConfig,
+    pub channels: ChannelsConfig,
+    pub websocket: WebSocketConfig,
+    pub cache: CacheConfig,
+    pub history: HistoryConfig,
+    pub reliability: ReliabilityConfig,
+    pub timesync: TimeSyncConfig,
+    pub liveness: LivenessConfig,
+    pub commands: CommandsConfig,
+    pub recorder: RecorderConfig,
+    pub capture: CaptureConfig,
+    pub log: LogConfig,
+    /// Known Realtime nodes; more are learned from their heartbeats.
+    pub peers: Vec<PeerConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub bind: SocketAddr,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([0, 0, 0, 0], 3000)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UdpConfig {
+    pub listen: SocketAddr,
+    /// Realtime node commands are sent to; may be a hostname.
+    pub realtime_host: String,
+}
+
+impl Default for UdpConfig {
+    fn default() -> Self {
+        Self {
+            listen: SocketAddr::from(([0, 0, 0, 0], 5000)),
+            realtime_host: "127.0.0.1:5001".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PeerConfig {
+    pub node_id: String,
+    /// `host:port` the node receives commands on.
+    pub addr: String,
+    /// Actuator commands whose `actuator_id` starts with one of these are
+    /// routed to this node when they carry no `Header.dest`.
+    #[serde(default)]
+    pub actuator_prefixes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChannelsConfig {
+    /// Messages a WebSocket client may fall behind before it is resynchronised.
+    pub broadcast_capacity: usize,
+    pub udp_outbound_capacity: usize,
+}
+
+impl Default for ChannelsConfig {
+    fn default() -> Self {
+        Self {
+            broadcast_capacity: 100,
+            udp_outbound_capacity: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebSocketConfig {
+    /// Pending control requests/events per client.
+    pub client_queue: usize,
+    /// How often clients are told how many messages were coalesced.
+    pub coalesce_report_ms: u64,
+    /// How often clients get link sequence statistics, when they changed.
+    pub stats_report_ms: u64,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self {
+            client_queue: 32,
+            coalesce_report_ms: 1000,
+            stats_report_ms: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Messages kept per slot for `TRANSIENT_LOCAL` with `history_keep_all`;
+    /// also caps `QosProfile.depth`.
+    pub keep_all_limit: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self { keep_all_limit: 1000 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// Most recent inbound messages kept for `/api/history`; 0 disables it.
+    pub capacity: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self { capacity: 10000 }
+    }
+}
+
+/// Retransmission of commands whose `Header.qos.reliability` is `RELIABLE`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReliabilityConfig {
+    /// Delay before the first retransmission; doubled after every attempt.
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// Time after the first send at which an unacknowledged command fails.
+    pub deadline_ms: u64,
+    /// How often pending commands are checked for retransmission.
+    pub retry_tick_ms: u64,
+}
+
+impl Default for ReliabilityConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff_ms: 100,
+            max_backoff_ms: 2000,
+            deadline_ms: 5000,
+            retry_tick_ms: 20,
+        }
+    }
+}
+
+/// NTP-style `TimeSync` exchanges used to estimate each peer's clock offset.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeSyncConfig {
+    /// Time between exchanges; 0 disables them.
+    pub interval_ms: u64,
+    /// Recent samples per peer; the one with the lowest round trip is used.
+    pub window: usize,
+}
+
+impl Default for TimeSyncConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: 1000,
+            window: 8,
+        }
+    }
+}
+
+/// Heartbeat tracking of Realtime nodes and the backend's own heartbeat.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LivenessConfig {
+    /// Silence after which a node is reported stale.
+    pub stale_after_ms: u64,
+    /// Silence after which a node is reported dead.
+    pub dead_after_ms: u64,
+    pub check_interval_ms: u64,
+    /// Period of the backend's heartbeat to every peer; 0 disables it.
+    pub heartbeat_interval_ms: u64,
+}
+
+impl Default for LivenessConfig {
+    fn default() -> Self {
+        Self {
+            stale_after_ms: 3000,
+            dead_after_ms: 10000,
+            check_interval_ms: 500,
+            heartbeat_interval_ms: 1000,
+        }
+    }
+}
+
+/// On-disk recording of every message received from the Realtime side.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecorderConfig {
+    /// Each recording is a directory of numbered files under this one.
+    pub dir: PathBuf,
+    /// Start recording when the backend starts.
+    pub autostart: bool,
+    /// A new file is started once the current one reaches this size.
+    pub max_file_bytes: u64,
+    /// A new file is started once the current one spans this long; 0
+    /// disables time-based rotation.
+    pub max_file_secs: u64,
+    /// Minimum spacing of index entries.
+    pub index_interval_ms: u64,
+    pub flush_interval_ms: u64,
+    /// Records waiting for the writer; more are dropped and counted.
+    pub queue_capacity: usize,
+}
+
+impl Default for RecorderConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("recordings"),
+            autostart: false,
+            max_file_bytes: 64 * 1024 * 1024,
+            max_file_secs: 600,
+            index_interval_ms: 1000,
+            flush_interval_ms: 1000,
+            queue_capacity: 4096,
+        }
+    }
+}
+
+/// pcap capture of raw UDP datagrams in both directions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureConfig {
+    /// File to capture to, replaced on startup; capture is off when unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    pub flush_interval_ms: u64,
+    /// Datagrams waiting for the capture writer; more are left out.
+    pub queue_capacity: usize,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            flush_interval_ms: 1000,
+            queue_capacity: 4096,
+        }
+    }
+}
+
+/// Checks applied to commands from WebSocket and REST clients before they
+/// are sent to the Realtime side.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommandsConfig {
+    /// Reject actuator commands for actuators missing from `actuators`.
+    pub reject_unknown_actuators: bool,
+    /// Limits per `ActuatorCommand.actuator_id`.
+    pub actuators: BTreeMap<String, ActuatorLimits>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActuatorLimits {
+    /// Bounds for numeric commands; `on` is not range checked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    /// Allowed `ActuatorCommand.command` variants; empty allows all.
+    pub commands: Vec<CommandVariant>,
+}
+
+/// The `ActuatorCommand.command` oneof cases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommandVariant {
+    Position,
+    Velocity,
+    Torque,
+    On,
+    Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// `EnvFilter` directive; `RUST_LOG` takes precedence when set.
+    pub level: String,
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+impl Config {
+    /// Builds the effective configuration for this process.
+    pub fn load(cli: &Cli) -> Result<Self, ConfigError> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.apply(&cli.overrides);
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        toml::from_str(&text).map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    fn apply(&mut self, overrides: &Overrides) {
+        if let Some(bind) = overrides.http_bind {
+            self.http.bind = bind;
+        }
+        if let Some(listen) = overrides.udp_listen {
+            self.udp.listen = listen;
+        }
+        if let Some(host) = &overrides.realtime_host {
+            self.udp.realtime_host = host.clone();
+        }
+        if let Some(capacity) = overrides.broadcast_capacity {
+            self.channels.broadcast_capacity = capacity;
+        }
+        if let Some(capacity) = overrides.udp_outbound_capacity {
+            self.channels.udp_outbound_capacity = capacity;
+        }
+        if let Some(path) = &overrides.capture {
+            self.capture.path = Some(path.clone());
+        }
+        if let Some(level) = &overrides.log_level {
+            self.log.level = level.clone();
+        }
+        if let Some(format) = overrides.log_format {
+            self.log.format = format;
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        validate_host_port("udp.realtime_host", &self.udp.realtime_host)?;
+        let mut node_ids = std::collections::HashSet::new();
+        for peer in &self.peers {
+            if peer.node_id.is_empty() || peer.node_id == BROADCAST_DEST {
+                return Err(ConfigError::Invalid(format!(
+                    "peers: invalid node_id {:?}",
+                    peer.node_id
+                )));
+            }
+            if !node_ids.insert(peer.node_id.as_str()) {
+                return Err(ConfigError::Invalid(format!(
+                    "peers: duplicate node_id {:?}",
+                    peer.node_id
+                )));
+            }
+            validate_host_port(&format!("peers.{}.addr", peer.node_id), &peer.addr)?;
+        }
+        validate_capacity("channels.broadcast_capacity", self.channels.broadcast_capacity)?;
+        validate_capacity("channels.udp_outbound_capacity", self.channels.udp_outbound_capacity)?;
+        validate_capacity("websocket.client_queue", self.websocket.client_queue)?;
+        validate_capacity("cache.keep_all_limit", self.cache.keep_all_limit)?;
+        if self.history.capacity > MAX_CAPACITY {
+            return Err(ConfigError::Invalid(format!(
+                "history.capacity must be at most {}",
+                MAX_CAPACITY
+            )));
+        }
+        if self.websocket.coalesce_report_ms == 0 || self.websocket.stats_report_ms == 0 {
+            return Err(ConfigError::Invalid(
+                "websocket.coalesce_report_ms and stats_report_ms must be greater than 0".to_string(),
+            ));
+        }
+        let reliability = &self.reliability;
+        if reliability.initial_backoff_ms == 0 || reliability.retry_tick_ms == 0 {
+            return Err(ConfigError::Invalid(
+                "reliability.initial_backoff_ms and retry_tick_ms must be greater than 0".to_string(),
+            ));
+        }
+        if reliability.max_backoff_ms < reliability.initial_backoff_ms {
+            return Err(ConfigError::Invalid(
+                "reliability.max_backoff_ms must not be below initial_backoff_ms".to_string(),
+            ));
+        }
+        if reliability.deadline_ms <= reliability.initial_backoff_ms {
+            return Err(ConfigError::Invalid(
+                "reliability.deadline_ms must be greater than initial_backoff_ms".to_string(),
+            ));
+        }
+        if self.timesync.window == 0 {
+            return Err(ConfigError::Invalid(
+                "timesync.window must be greater than 0".to_string(),
+            ));
+        }
+        let liveness = &self.liveness;
+        if liveness.check_interval_ms == 0 || liveness.stale_after_ms == 0 {
+            return Err(ConfigError::Invalid(
+                "liveness.check_interval_ms and stale_after_ms must be greater than 0".to_string(),
+            ));
+        }
+        if liveness.dead_after_ms <= liveness.stale_after_ms {
+            return Err(ConfigError::Invalid(
+                "liveness.dead_after_ms must be greater than stale_after_ms".to_string(),
+            ));
+        }
+        for (id, limits) in &self.commands.actuators {
+            let finite = |v: Option<f64>| v.is_none_or(f64::is_finite);
+            if !finite(limits.min) || !finite(limits.max) {
+                return Err(ConfigError::Invalid(format!(
+                    "commands.actuators.{}: min and max must be finite",
+                    id
+                )));
+            }
+            if let (Some(min), Some(max)) = (limits.min, limits.max) {
+                if min > max {
+                    return Err(ConfigError::Invalid(format!(
+                        "commands.actuators.{}: min must not be above max",
+                        id
+                    )));
+                }
+            }
+        }
+        let recorder = &self.recorder;
+        if recorder.dir.as_os_str().is_empty() {
+            return Err(ConfigError::Invalid("recorder.dir must not be empty".to_string()));
+        }
+        if recorder.max_file_bytes == 0
+            || recorder.index_interval_ms == 0
+            || recorder.flush_interval_ms == 0
+            || recorder.queue_capacity == 0
+        {
+            return Err(ConfigError::Invalid(
+                "recorder.max_file_bytes, index_interval_ms, flush_interval_ms and queue_capacity must be greater than 0"
+                    .to_string(),
+            ));
+        }
+        if self.capture.flush_interval_ms == 0 || self.capture.queue_capacity == 0 {
+            return Err(ConfigError::Invalid(
+                "capture.flush_interval_ms and queue_capacity must be greater than 0".to_string(),
+            ));
+        }
+        tracing_subscriber::EnvFilter::try_new(&self.log.level)
+            .map_err(|e| ConfigError::Invalid(format!("log.level: {}", e)))?;
+        Ok(())
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).expect("Config is always representable as TOML")
+    }
+}
+
+/// Upper bound for channel capacities; tokio panics on zero or huge values.
+const MAX_CAPACITY: usize = 1 << 20;
+
+fn validate_capacity(name: &str, value: usize) -> Result<(), ConfigError> {
+    if value == 0 || value > MAX_CAPACITY {
+        return Err(ConfigError::Invalid(format!(
+            "{} must be between 1 and {}",
+            name, MAX_CAPACITY
+        )));
+    }
+    Ok(())
+}
+
+/// Checks `host:port` syntax without resolving the host.
+fn validate_host_port(name: &str, value: &str) -> Result<(), ConfigError> {
+    match value.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(()),
+        _ => Err(ConfigError::Invalid(format!(
+            "{} must be host:port, got {:?}",
+            name, value
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_are_valid() {
+        let config = Config::default();
+        config.validate().unwrap();
+        assert_eq!(config.http.bind.port(), 3000);
+        assert_eq!(config.udp.listen.port(), 5000);
+    }
+
+    #[test]
+    fn test_partial_file_keeps_defaults() {
+        let config: Config = toml::from_str(
+            r#"
+            [udp]
+            realtime_host = "hil-rig:6001"
+
+            [log]
+            format = "json"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.udp.realtime_host, "hil-rig:6001");
+        assert_eq!(config.udp.listen.port(), 5000);
+        assert_eq!(config.log.format, LogFormat::Json);
+        assert_eq!(config.channels.broadcast_capacity, 100);
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        assert!(toml::from_str::<Config>("[udp]\nlisten_port = 5000\n").is_err());
+    }
+
+    #[test]
+    fn test_cli_overrides_file() {
+        let cli = Cli::parse_from([
+            "backend",
+            "--http-bind",
+            "127.0.0.1:8080",
+            "--log-format",
+            "json",
+            "--capture",
+            "link.pcap",
+        ]);
+        let mut config = Config::default();
+        config.apply(&cli.overrides);
+        assert_eq!(config.http.bind, "127.0.0.1:8080".parse().unwrap());
+        assert_eq!(config.capture.path, Some(PathBuf::from("link.pcap")));
+        assert_eq!(config.log.format, LogFormat::Json);
+    }
+
+    #[test]
+    fn test_peers_table() {
+        let config: Config = toml::from_str(
+            r#"
+            [[peers]]
+            node_id = "motion"
+            addr = "10.0.0.2:5001"
+            actuator_prefixes = ["joint_"]
+
+            [[peers]]
+            node_id = "power"
+            addr = "power-board:5001"
+            "#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.peers.len(), 2);
+        assert!(config.peers[1].actuator_prefixes.is_empty());
+
+        let mut duplicate = config.clone();
+        duplicate.peers[1].node_id = "motion".to_string();
+        assert!(duplicate.validate().is_err());
+    }
+
+    #[test]
+    fn test_actuator_limits_table() {
+        let config: Config = toml::from_str(
+            r#"
+            [commands.actuators.joint_1]
+            min = -1.5
+            max = 1.5
+            commands = ["position", "velocity"]
+
+            [commands.actuators.gripper]
+            commands = ["on"]
+            "#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        let joint = &config.commands.actuators["joint_1"];
+        assert_eq!((joint.min, joint.max), (Some(-1.5), Some(1.5)));
+        assert_eq!(joint.commands, [CommandVariant::Position, CommandVariant::Velocity]);
+        assert_eq!(config.commands.actuators["gripper"].max, None);
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        let mut config = Config::default();
+        config.udp.realtime_host = "no-port".to_string();
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.channels.broadcast_capacity = 0;
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.reliability.max_backoff_ms = 10;
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.liveness.dead_after_ms = config.liveness.stale_after_ms;
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.recorder.max_file_bytes = 0;
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.commands.actuators.insert(
+            "joint_1".to_string(),
+            ActuatorLimits { min: Some(1.0), max: Some(-1.0), ..Default::default() },
+        );
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_example_file_is_valid() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("config.example.toml");
+        Config::from_file(&path).unwrap().validate().unwrap();
+    }
+
+    #[test]
+    fn test_printed_config_round_trips() {
+        let text = Config::default().to_toml();
+        let parsed: Config = toml::from_str(&text).unwrap();
+        assert_eq!(parsed.to_toml(), text);
+    }
+}
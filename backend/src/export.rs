@@ -0,0 +1,718 @@
+//! Export of recorded sensor data as CSV or Parquet tables for analysis.
+//!
+//! Every `SensorBatch` in the chosen time range becomes one row, stamped
+//! with its reception time and source. Readings are flattened into one
+//! column per numeric field, named after the sensor:
+//!
+//! ```text
+//! scalar        temp
+//! vector        accel.0  accel.1  accel.2
+//! pose          arm.position.0 ... arm.orientation.3
+//! velocity      base.linear.0 ... base.angular.2
+//! temperature   board.ambient  board.cpu  board.board
+//! guidance      nav.heading_deg  nav.pitch_deg  nav.roll_deg  nav.yaw_rate_deg_s
+//! speed         wheel.linear_speed_mps  wheel.angular_speed_rps
+//! ```
+//!
+//! Columns appear in the order they are first seen; a sensor missing from
+//! a batch leaves its cells empty (null in Parquet). A sensor column whose
+//! name is already taken, e.g. by `time` or `source`, gets `_` prefixes
+//! until it is unique.
+//!
+//! A recording is read twice: once to find its columns, then again to
+//! write the rows a bounded batch at a time, so exports of any length run
+//! in constant memory and HTTP downloads are streamed.
+
+use crate::config::Config;
+use crate::recorder::{self, RecordingReader};
+use clap::{Args, ValueEnum};
+use parquet::basic::{Compression, LogicalType, Repetition, TimeUnit, Type as PhysicalType};
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::Type;
+use serde::Deserialize;
+use shared::proto::sensor_reading::Type as ReadingType;
+use shared::proto::SensorReading;
+use shared::ws::glob_match;
+use shared::MessageWrapper;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+/// Rows held in memory at once by default; also the Parquet row group size.
+const BATCH_ROWS: usize = 8192;
+
+/// Bytes per chunk handed on by `ChannelWriter`.
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// Columns in front of the sensor columns.
+const FIXED_COLUMNS: [&str; 2] = ["time", "source"];
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("No recording named {0:?}")]
+    NotFound(String),
+    #[error("Export I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Parquet error: {0}")]
+    Parquet(#[from] ParquetError),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// Which part of a recording to export.
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    /// First reception time to include, in microseconds since the Unix epoch.
+    pub since: Option<i64>,
+    /// Last reception time to include.
+    pub until: Option<i64>,
+    /// Sensor id glob patterns; empty exports every sensor.
+    pub sensors: Vec<String>,
+}
+
+impl ExportFilter {
+    fn matches_sensor(&self, sensor_id: &str) -> bool {
+        self.sensors.is_empty() || self.sensors.iter().any(|p| glob_match(p, sensor_id))
+    }
+}
+
+/// One exported `SensorBatch`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    pub received_at_us: i64,
+    pub source: String,
+    /// By column; shorter than the column list when later columns are empty.
+    pub values: Vec<Option<f64>>,
+}
+
+/// Sensor column names, in the order they are first seen.
+#[derive(Debug, Default)]
+struct Columns {
+    names: Vec<String>,
+    /// Column of every flattened field name.
+    index: HashMap<String, usize>,
+}
+
+impl Columns {
+    fn add(&mut self, field: String) {
+        let names = &mut self.names;
+        self.index.entry(field).or_insert_with_key(|field| {
+            let mut column = field.clone();
+            while FIXED_COLUMNS.contains(&column.as_str()) || names.contains(&column) {
+                column.insert(0, '_');
+            }
+            names.push(column);
+            names.len() - 1
+        });
+    }
+
+    /// The row for a batch with `fields`. Fields without a column, from a
+    /// recording that grew after it was scanned, are left out.
+    fn row(&self, received_at_us: i64, source: String, fields: Vec<(String, f64)>) -> Option<Row> {
+        let mut values = Vec::new();
+        for (field, value) in fields {
+            let Some(&column) = self.index.get(&field) else {
+                continue;
+            };
+            if values.len() <= column {
+                values.resize(column + 1, None);
+            }
+            values[column] = Some(value);
+        }
+        if values.is_empty() {
+            return None;
+        }
+        Some(Row {
+            received_at_us,
+            source,
+            values,
+        })
+    }
+}
+
+/// Numeric fields of `reading`, named as described in the module docs.
+/// Readings without an explicit type are flattened by what they carry.
+fn flatten(reading: &SensorReading, out: &mut Vec<(String, f64)>) {
+    let id = &reading.sensor_id;
+    let indexed = |out: &mut Vec<(String, f64)>, prefix: String, values: &[f64]| {
+        out.extend(values.iter().enumerate().map(|(i, v)| (format!("{}.{}", prefix, i), *v)));
+    };
+    let kind = match reading.r#type() {
+        ReadingType::Unspecified if reading.pose.is_some() => ReadingType::Pose6dof,
+        ReadingType::Unspecified if reading.velocity.is_some() => ReadingType::Velocity6dof,
+        ReadingType::Unspecified if reading.temperature.is_some() => ReadingType::Temperature,
+        ReadingType::Unspecified if reading.guidance.is_some() => ReadingType::Guidance,
+        ReadingType::Unspecified if reading.speed.is_some() => ReadingType::Speed,
+        ReadingType::Unspecified if !reading.vector.is_empty() => ReadingType::Vector,
+        ReadingType::Unspecified => ReadingType::Scalar,
+        kind => kind,
+    };
+    match kind {
+        ReadingType::Scalar => out.push((id.clone(), reading.scalar)),
+        ReadingType::Vector => indexed(out, id.clone(), &reading.vector),
+        ReadingType::Pose6dof => {
+            let pose = reading.pose.clone().unwrap_or_default();
+            indexed(out, format!("{}.position", id), &pose.position);
+            indexed(out, format!("{}.orientation", id), &pose.orientation);
+        }
+        ReadingType::Velocity6dof => {
+            let velocity = reading.velocity.clone().unwrap_or_default();
+            indexed(out, format!("{}.linear", id), &velocity.linear);
+            indexed(out, format!("{}.angular", id), &velocity.angular);
+        }
+        ReadingType::Temperature => {
+            let t = reading.temperature.unwrap_or_default();
+            out.extend([("ambient", t.ambient), ("cpu", t.cpu), ("board", t.board)].map(|(f, v)| (format!("{}.{}", id, f), v)));
+        }
+        ReadingType::Guidance => {
+            let g = reading.guidance.unwrap_or_default();
+            out.extend(
+                [
+                    ("heading_deg", g.heading_deg),
+                    ("pitch_deg", g.pitch_deg),
+                    ("roll_deg", g.roll_deg),
+                    ("yaw_rate_deg_s", g.yaw_rate_deg_s),
+                ]
+                .map(|(f, v)| (format!("{}.{}", id, f), v)),
+            );
+        }
+        ReadingType::Speed => {
+            let s = reading.speed.unwrap_or_default();
+            out.extend(
+                [("linear_speed_mps", s.linear_speed_mps), ("angular_speed_rps", s.angular_speed_rps)]
+                    .map(|(f, v)| (format!("{}.{}", id, f), v)),
+            );
+        }
+        // Images and opaque payloads have no numeric fields to export
+        ReadingType::Image | ReadingType::Binary | ReadingType::Unspecified => {}
+    }
+}
+
+/// Calls `visit` with the reception time, source and flattened fields of
+/// every sensor batch of recording `name` at `path` that `filter` selects.
+/// Frames that fail to decode and batches without fields are skipped.
+fn scan(
+    path: &Path,
+    name: &str,
+    filter: &ExportFilter,
+    mut visit: impl FnMut(i64, String, Vec<(String, f64)>) -> Result<(), ExportError>,
+) -> Result<(), ExportError> {
+    let mut reader = RecordingReader::open(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => ExportError::NotFound(name.to_string()),
+        _ => ExportError::Io(e),
+    })?;
+    let mut next = match filter.since {
+        Some(since) => reader.seek(since)?,
+        None => reader.next_record()?,
+    };
+    while let Some(record) = next {
+        if filter.until.is_some_and(|until| record.received_at_us > until) {
+            break;
+        }
+        if let Ok((_, MessageWrapper::SensorBatch(batch))) = MessageWrapper::decode_frame(&record.frame) {
+            let mut fields = Vec::new();
+            for reading in batch.readings.iter().filter(|reading| filter.matches_sensor(&reading.sensor_id)) {
+                flatten(reading, &mut fields);
+            }
+            if !fields.is_empty() {
+                let source = batch.header.map(|header| header.source).unwrap_or_default();
+                visit(record.received_at_us, source, fields)?;
+            }
+        }
+        next = reader.next_record()?;
+    }
+    Ok(())
+}
+
+/// The part of a recording selected by a filter, with its sensor columns
+/// known, written out a bounded batch of rows at a time so memory does not
+/// grow with the recording.
+pub struct Export {
+    name: String,
+    path: PathBuf,
+    filter: ExportFilter,
+    columns: Columns,
+    /// Rows held in memory at once; also the Parquet row group size.
+    pub batch_rows: usize,
+}
+
+impl Export {
+    /// Reads recording `name` under `dir` once to find the sensor columns
+    /// that `filter` selects.
+    pub fn prepare(dir: &Path, name: &str, filter: ExportFilter) -> Result<Self, ExportError> {
+        if !recorder::valid_name(name) {
+            return Err(ExportError::NotFound(name.to_string()));
+        }
+        let path = dir.join(name);
+        let mut columns = Columns::default();
+        scan(&path, name, &filter, |_, _, fields| {
+            for (field, _) in fields {
+                columns.add(field);
+            }
+            Ok(())
+        })?;
+        Ok(Self {
+            name: name.to_string(),
+            path,
+            filter,
+            columns,
+            batch_rows: BATCH_ROWS,
+        })
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns.names
+    }
+
+    /// Reads the recording again, calling `write` with at most `batch_rows`
+    /// rows at a time.
+    pub fn for_each_batch(&self, mut write: impl FnMut(&[Row]) -> Result<(), ExportError>) -> Result<(), ExportError> {
+        let batch_rows = self.batch_rows.max(1);
+        let mut batch = Vec::with_capacity(batch_rows);
+        scan(&self.path, &self.name, &self.filter, |received_at_us, source, fields| {
+            batch.extend(self.columns.row(received_at_us, source, fields));
+            if batch.len() >= batch_rows {
+                write(&batch)?;
+                batch.clear();
+            }
+            Ok(())
+        })?;
+        if !batch.is_empty() {
+            write(&batch)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the export in `format` to `out`, flushing it at the end.
+    pub fn write(&self, format: ExportFormat, out: impl Write + Send) -> Result<(), ExportError> {
+        match format {
+            ExportFormat::Csv => self.write_csv(out),
+            ExportFormat::Parquet => self.write_parquet(out),
+        }
+    }
+
+    /// CSV with a header line. Times are RFC 3339 in UTC.
+    fn write_csv(&self, mut out: impl Write) -> Result<(), ExportError> {
+        let mut header = FIXED_COLUMNS.map(str::to_string).to_vec();
+        header.extend(self.columns().iter().map(|column| csv_field(column)));
+        writeln!(out, "{}", header.join(","))?;
+        let columns = self.columns().len();
+        self.for_each_batch(|rows| {
+            for row in rows {
+                write!(out, "{},{}", format_time(row.received_at_us), csv_field(&row.source))?;
+                for column in 0..columns {
+                    match row.values.get(column).copied().flatten() {
+                        Some(value) => write!(out, ",{}", value)?,
+                        None => out.write_all(b",")?,
+                    }
+                }
+                out.write_all(b"\n")?;
+            }
+            Ok(())
+        })?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Parquet with one row group per batch: `time` is a UTC microsecond
+    /// timestamp, `source` a string and every sensor column an optional double.
+    fn write_parquet(&self, mut out: impl Write + Send) -> Result<(), ExportError> {
+        let mut fields = vec![
+            Arc::new(
+                Type::primitive_type_builder(FIXED_COLUMNS[0], PhysicalType::INT64)
+                    .with_repetition(Repetition::REQUIRED)
+                    .with_logical_type(Some(LogicalType::timestamp(true, TimeUnit::MICROS)))
+                    .build()?,
+            ),
+            Arc::new(
+                Type::primitive_type_builder(FIXED_COLUMNS[1], PhysicalType::BYTE_ARRAY)
+                    .with_repetition(Repetition::REQUIRED)
+                    .with_logical_type(Some(LogicalType::String))
+                    .build()?,
+            ),
+        ];
+        for column in self.columns() {
+            fields.push(Arc::new(
+                Type::primitive_type_builder(column, PhysicalType::DOUBLE)
+                    .with_repetition(Repetition::OPTIONAL)
+                    .build()?,
+            ));
+        }
+        let schema = Type::group_type_builder("sensors").with_fields(fields).build()?;
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let mut writer = SerializedFileWriter::new(&mut out, Arc::new(schema), Arc::new(properties))?;
+
+        self.for_each_batch(|rows| {
+            let mut group = writer.next_row_group()?;
+            let mut column = 0;
+            while let Some(mut writer) = group.next_column()? {
+                match column {
+                    0 => {
+                        let times: Vec<i64> = rows.iter().map(|row| row.received_at_us).collect();
+                        writer.typed::<Int64Type>().write_batch(&times, None, None)?;
+                    }
+                    1 => {
+                        let sources: Vec<ByteArray> = rows.iter().map(|row| row.source.as_str().into()).collect();
+                        writer.typed::<ByteArrayType>().write_batch(&sources, None, None)?;
+                    }
+                    _ => {
+                        let cells: Vec<Option<f64>> = rows
+                            .iter()
+                            .map(|row| row.values.get(column - 2).copied().flatten())
+                            .collect();
+                        let values: Vec<f64> = cells.iter().flatten().copied().collect();
+                        let levels: Vec<i16> = cells.iter().map(|cell| i16::from(cell.is_some())).collect();
+                        writer.typed::<DoubleType>().write_batch(&values, Some(&levels), None)?;
+                    }
+                }
+                writer.close()?;
+                column += 1;
+            }
+            group.close()?;
+            Ok(())
+        })?;
+        writer.close()?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// `value`, quoted when it holds a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn format_time(micros: i64) -> String {
+    chrono::DateTime::from_timestamp_micros(micros)
+        .map(|time| time.to_rfc3339_opts(chrono::SecondsFormat::Micros, true))
+        .unwrap_or_else(|| micros.to_string())
+}
+
+/// Hands what is written to an async receiver in chunks, for streaming an
+/// export from a blocking thread as an HTTP body.
+pub struct ChannelWriter {
+    tx: mpsc::Sender<io::Result<Vec<u8>>>,
+    buf: Vec<u8>,
+}
+
+impl ChannelWriter {
+    pub fn new(tx: mpsc::Sender<io::Result<Vec<u8>>>) -> Self {
+        Self {
+            tx,
+            buf: Vec::with_capacity(CHUNK_LEN),
+        }
+    }
+
+    fn send(&mut self) -> io::Result<()> {
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK_LEN));
+        self.tx
+            .blocking_send(Ok(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "export receiver is gone"))
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CHUNK_LEN {
+            self.send()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        self.send()
+    }
+}
+
+/// Parses an RFC 3339 time or integer microseconds since the Unix epoch.
+pub fn parse_time(value: &str) -> Result<i64, String> {
+    if let Ok(micros) = value.parse::<i64>() {
+        return Ok(micros);
+    }
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|time| time.timestamp_micros())
+        .map_err(|e| format!("Invalid time {:?}: {}", value, e))
+}
+
+/// `export` subcommand: writes a recording's sensor data to a file.
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// Recording name under `recorder.dir`
+    pub name: String,
+
+    /// Output format; taken from the output file extension when omitted
+    #[arg(long, value_enum)]
+    pub format: Option<ExportFormat>,
+
+    /// First reception time to export (RFC 3339 or epoch microseconds)
+    #[arg(long, value_parser = parse_time)]
+    pub since: Option<i64>,
+
+    /// Last reception time to export (RFC 3339 or epoch microseconds)
+    #[arg(long, value_parser = parse_time)]
+    pub until: Option<i64>,
+
+    /// Sensor id glob patterns to export; every sensor when omitted
+    #[arg(long, value_delimiter = ',')]
+    pub sensors: Vec<String>,
+
+    /// Output file; standard output when omitted
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// Runs the `export` subcommand, returning the process exit code.
+pub fn run(config: &Config, args: &ExportArgs) -> i32 {
+    let format = args.format.unwrap_or_else(|| {
+        match args.output.as_deref().and_then(Path::extension) {
+            Some(ext) if ext == "parquet" => ExportFormat::Parquet,
+            _ => ExportFormat::Csv,
+        }
+    });
+    let filter = ExportFilter {
+        since: args.since,
+        until: args.until,
+        sensors: args.sensors.clone(),
+    };
+    let result = Export::prepare(&config.recorder.dir, &args.name, filter).and_then(|export| match &args.output {
+        Some(path) => export.write(format, io::BufWriter::new(std::fs::File::create(path)?)),
+        None => export.write(format, io::BufWriter::new(io::stdout())),
+    });
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recorder::Recorder;
+    use crate::state::Envelope;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use shared::proto::{GuidanceData, Header, Heartbeat, Pose6Dof, SensorBatch};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    fn batch(source: &str, readings: Vec<SensorReading>) -> MessageWrapper {
+        MessageWrapper::SensorBatch(SensorBatch {
+            header: Some(Header {
+                source: source.to_string(),
+                ..Default::default()
+            }),
+            readings,
+        })
+    }
+
+    fn scalar(id: &str, value: f64) -> SensorReading {
+        SensorReading {
+            sensor_id: id.to_string(),
+            r#type: ReadingType::Scalar as i32,
+            scalar: value,
+            ..Default::default()
+        }
+    }
+
+    /// A recording directory holding `run`: two batches a second apart
+    /// around a heartbeat, the second adding a guidance sensor.
+    fn recorded(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("export-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut config = Config::default();
+        config.recorder.dir = dir.clone();
+        let recorder = Recorder::new(config.recorder);
+        recorder.start(Some("run"), SystemTime::now()).unwrap();
+        let guidance = SensorReading {
+            sensor_id: "nav".to_string(),
+            r#type: ReadingType::Guidance as i32,
+            guidance: Some(GuidanceData {
+                heading_deg: 90.0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let messages = [
+            batch("hub", vec![scalar("temp", 21.5)]),
+            MessageWrapper::Heartbeat(Heartbeat::default()),
+            batch("hub, north", vec![scalar("temp", 22.0), guidance]),
+        ];
+        for (secs, msg) in (1..).zip(messages) {
+            let frame = msg.to_bytes().unwrap();
+            let at = UNIX_EPOCH + Duration::from_secs(secs);
+            recorder.record(&Envelope::received_at(msg, at, None), &frame);
+        }
+        recorder.stop().unwrap();
+        dir
+    }
+
+    fn rows(export: &Export) -> Vec<Row> {
+        let mut rows = Vec::new();
+        export
+            .for_each_batch(|batch| {
+                rows.extend_from_slice(batch);
+                Ok(())
+            })
+            .unwrap();
+        rows
+    }
+
+    fn export(dir: &Path, filter: &ExportFilter, format: ExportFormat, batch_rows: usize) -> Vec<u8> {
+        let mut export = Export::prepare(dir, "run", filter.clone()).unwrap();
+        export.batch_rows = batch_rows;
+        let mut out = Vec::new();
+        export.write(format, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_flatten_variants() {
+        let mut fields = Vec::new();
+        let pose = SensorReading {
+            sensor_id: "arm".to_string(),
+            pose: Some(Pose6Dof {
+                position: vec![1.0, 2.0, 3.0],
+                orientation: vec![0.5],
+            }),
+            ..Default::default()
+        };
+        flatten(&pose, &mut fields);
+        let vector = SensorReading {
+            sensor_id: "accel".to_string(),
+            r#type: ReadingType::Vector as i32,
+            vector: vec![9.8, 0.0],
+            ..Default::default()
+        };
+        flatten(&vector, &mut fields);
+        let names: Vec<&str> = fields.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["arm.position.0", "arm.position.1", "arm.position.2", "arm.orientation.0", "accel.0", "accel.1"]
+        );
+        assert_eq!(fields[4].1, 9.8);
+    }
+
+    #[test]
+    fn test_collect_filters_time_and_sensors() {
+        let dir = recorded("collect");
+        let all = Export::prepare(&dir, "run", ExportFilter::default()).unwrap();
+        assert_eq!(all.columns(), ["temp", "nav.heading_deg", "nav.pitch_deg", "nav.roll_deg", "nav.yaw_rate_deg_s"]);
+        let table = rows(&all);
+        assert_eq!(table.len(), 2);
+        assert_eq!(table[0].values, [Some(21.5)]);
+        assert_eq!(table[1].values[1], Some(90.0));
+
+        let filter = ExportFilter {
+            since: Some(2_000_000),
+            until: Some(3_000_000),
+            sensors: vec!["n*".to_string()],
+        };
+        let selected = Export::prepare(&dir, "run", filter).unwrap();
+        assert_eq!(selected.columns().len(), 4);
+        let table = rows(&selected);
+        assert_eq!((table.len(), table[0].received_at_us), (1, 3_000_000));
+
+        let filter = ExportFilter {
+            until: Some(1_000_000),
+            sensors: vec!["nav".to_string()],
+            ..Default::default()
+        };
+        assert!(rows(&Export::prepare(&dir, "run", filter.clone()).unwrap()).is_empty());
+        assert!(matches!(Export::prepare(&dir, "missing", filter.clone()), Err(ExportError::NotFound(_))));
+        assert!(matches!(Export::prepare(&dir, "../run", filter), Err(ExportError::NotFound(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_sensor_columns_never_clash() {
+        let mut columns = Columns::default();
+        let fields: Vec<(String, f64)> = [("time", 1.0), ("source", 2.0), ("_time", 3.0)]
+            .map(|(field, value)| (field.to_string(), value))
+            .to_vec();
+        for (field, _) in &fields {
+            columns.add(field.clone());
+        }
+        assert_eq!(columns.names, ["_time", "_source", "__time"]);
+        let row = columns.row(0, "hub".to_string(), fields).unwrap();
+        assert_eq!(row.values, [Some(1.0), Some(2.0), Some(3.0)]);
+    }
+
+    #[test]
+    fn test_csv_output() {
+        let dir = recorded("csv");
+        let filter = ExportFilter {
+            sensors: vec!["temp".to_string(), "nav".to_string()],
+            ..Default::default()
+        };
+        let csv = String::from_utf8(export(&dir, &filter, ExportFormat::Csv, BATCH_ROWS)).unwrap();
+        // Batching only bounds memory; the output is the same
+        assert_eq!(export(&dir, &filter, ExportFormat::Csv, 1), csv.as_bytes());
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "time,source,temp,nav.heading_deg,nav.pitch_deg,nav.roll_deg,nav.yaw_rate_deg_s");
+        assert_eq!(lines[1], "1970-01-01T00:00:01.000000Z,hub,21.5,,,,");
+        assert_eq!(lines[2], "1970-01-01T00:00:03.000000Z,\"hub, north\",22,90,0,0,0");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parquet_output() {
+        let dir = recorded("parquet");
+        let bytes = export(&dir, &ExportFilter::default(), ExportFormat::Parquet, 1);
+        let reader = SerializedFileReader::new(axum::body::Bytes::from(bytes)).unwrap();
+        // One row group per batch
+        assert_eq!(reader.metadata().num_row_groups(), 2);
+        let metadata = reader.metadata().file_metadata();
+        assert_eq!(metadata.num_rows(), 2);
+        let names: Vec<&str> = metadata.schema_descr().columns().iter().map(|c| c.name()).collect();
+        assert_eq!(&names[..3], ["time", "source", "temp"]);
+
+        let rows: Vec<String> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| row.unwrap().to_string())
+            .collect();
+        assert!(rows[0].contains("temp: 21.5"), "{}", rows[0]);
+        assert!(rows[0].contains("nav.heading_deg: null"), "{}", rows[0]);
+        assert!(rows[1].contains("nav.heading_deg: 90.0"), "{}", rows[1]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
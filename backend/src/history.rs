@@ -0,0 +1,89 @@
+use crate::state::Envelope;
+use shared::MessageKind;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Bounded in-memory log of every message received from the Realtime side,
+/// oldest first, for `GET /api/history`.
+pub struct History {
+    entries: Mutex<VecDeque<Envelope>>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::with_capacity(capacity.min(4096))),
+            capacity,
+        }
+    }
+
+    pub fn push(&self, envelope: Envelope) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(envelope);
+    }
+
+    /// Messages of `kind` (any kind when `None`) received within
+    /// `[since, until]`, in microseconds since the Unix epoch.
+    pub fn query(&self, kind: Option<MessageKind>, since: Option<i64>, until: Option<i64>) -> Vec<Envelope> {
+        let entries = self.entries.lock().unwrap();
+        // Entries are in reception order, so skip straight to `since`
+        let start = since.map_or(0, |since| entries.partition_point(|e| e.meta.received_at_us < since));
+        entries
+            .range(start..)
+            .take_while(|e| until.is_none_or(|until| e.meta.received_at_us <= until))
+            .filter(|e| kind.is_none_or(|kind| e.msg.kind() == kind))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::proto::{Heartbeat, SystemStatus};
+    use shared::MessageWrapper;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn at(micros: u64, msg: MessageWrapper) -> Envelope {
+        Envelope::received_at(msg, UNIX_EPOCH + Duration::from_micros(micros), None)
+    }
+
+    fn times(entries: &[Envelope]) -> Vec<i64> {
+        entries.iter().map(|e| e.meta.received_at_us).collect()
+    }
+
+    #[test]
+    fn test_query_by_kind_and_window() {
+        let history = History::new(10);
+        for t in 1..=5 {
+            history.push(at(t * 10, MessageWrapper::Heartbeat(Heartbeat::default())));
+            history.push(at(t * 10 + 1, MessageWrapper::SystemStatus(SystemStatus::default())));
+        }
+        assert_eq!(times(&history.query(None, Some(20), Some(30))), [20, 21, 30]);
+        assert_eq!(
+            times(&history.query(Some(MessageKind::SystemStatus), Some(25), None)),
+            [31, 41, 51]
+        );
+        assert_eq!(times(&history.query(Some(MessageKind::Heartbeat), None, Some(19))), [10]);
+    }
+
+    #[test]
+    fn test_capacity_drops_oldest() {
+        let history = History::new(2);
+        for t in 1..=3 {
+            history.push(at(t, MessageWrapper::Heartbeat(Heartbeat::default())));
+        }
+        assert_eq!(times(&history.query(None, None, None)), [2, 3]);
+
+        let disabled = History::new(0);
+        disabled.push(at(1, MessageWrapper::Heartbeat(Heartbeat::default())));
+        assert!(disabled.query(None, None, None).is_empty());
+    }
+}
@@ -0,0 +1,210 @@
+use crate::config::LivenessConfig;
+use crate::peers::BROADCAST_DEST;
+use crate::state::AppState;
+use crate::timesync::{self, BACKEND_SOURCE};
+use crate::udp::Outbound;
+use dashmap::DashMap;
+use shared::proto::{Header, Heartbeat};
+use shared::ws::{Liveness, NodeInfo, ServerEvent};
+use shared::MessageWrapper;
+use std::time::{Duration, SystemTime};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// A node moving from one liveness state to another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transition {
+    pub node_id: String,
+    pub from: Option<Liveness>,
+    pub to: Liveness,
+}
+
+impl Transition {
+    pub fn event(&self) -> ServerEvent {
+        ServerEvent::NodeState {
+            node_id: self.node_id.clone(),
+            state: self.to,
+            previous: self.from,
+        }
+    }
+}
+
+struct Node {
+    info: NodeInfo,
+    last_seen: Instant,
+}
+
+/// Tracks Realtime nodes by `Heartbeat.node_id` and ages them from alive to
+/// stale to dead when their heartbeats stop.
+pub struct LivenessMonitor {
+    nodes: DashMap<String, Node>,
+    stale_after: Duration,
+    dead_after: Duration,
+}
+
+impl LivenessMonitor {
+    pub fn new(config: &LivenessConfig) -> Self {
+        Self {
+            nodes: DashMap::new(),
+            stale_after: Duration::from_millis(config.stale_after_ms),
+            dead_after: Duration::from_millis(config.dead_after_ms),
+        }
+    }
+
+    /// Records a heartbeat; returns a transition when the node is new or was
+    /// not alive.
+    pub fn record(&self, hb: &Heartbeat, now: Instant, wall: SystemTime) -> Option<Transition> {
+        if hb.node_id.is_empty() {
+            return None;
+        }
+        let info = NodeInfo {
+            node_id: hb.node_id.clone(),
+            state: Liveness::Alive,
+            status: hb.status.clone(),
+            uptime_sec: hb.uptime_sec,
+            last_seen_us: timesync::to_micros(wall),
+        };
+        let previous = self
+            .nodes
+            .insert(hb.node_id.clone(), Node { info, last_seen: now })
+            .map(|node| node.info.state);
+        (previous != Some(Liveness::Alive)).then(|| Transition {
+            node_id: hb.node_id.clone(),
+            from: previous,
+            to: Liveness::Alive,
+        })
+    }
+
+    /// Ages every node to `now` and returns the ones that changed state.
+    pub fn check(&self, now: Instant) -> Vec<Transition> {
+        let mut transitions = Vec::new();
+        for mut node in self.nodes.iter_mut() {
+            let silent = now.saturating_duration_since(node.last_seen);
+            let state = if silent >= self.dead_after {
+                Liveness::Dead
+            } else if silent >= self.stale_after {
+                Liveness::Stale
+            } else {
+                Liveness::Alive
+            };
+            if state != node.info.state {
+                transitions.push(Transition {
+                    node_id: node.info.node_id.clone(),
+                    from: Some(node.info.state),
+                    to: state,
+                });
+                node.info.state = state;
+            }
+        }
+        transitions.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        transitions
+    }
+
+    pub fn list(&self) -> Vec<NodeInfo> {
+        let mut nodes: Vec<NodeInfo> = self.nodes.iter().map(|n| n.info.clone()).collect();
+        nodes.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        nodes
+    }
+}
+
+/// Logs a transition and tells every WebSocket client about it.
+pub fn publish(state: &AppState, transition: &Transition) {
+    match transition.to {
+        Liveness::Alive => info!("Realtime node {} is alive", transition.node_id),
+        other => warn!("Realtime node {} is {:?}", transition.node_id, other),
+    }
+    let _ = state.events.send(transition.event());
+}
+
+/// Periodically ages nodes that stopped sending heartbeats.
+pub async fn liveness_loop(state: AppState) {
+    let period = Duration::from_millis(state.config.liveness.check_interval_ms);
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        for transition in state.liveness.check(Instant::now()) {
+            publish(&state, &transition);
+        }
+    }
+}
+
+/// Sends the backend's own heartbeat to every peer so they can tell when the
+/// simulation side has gone away.
+pub async fn heartbeat_loop(state: AppState) {
+    let period = Duration::from_millis(state.config.liveness.heartbeat_interval_ms);
+    let mut interval = tokio::time::interval(period);
+    let started = Instant::now();
+    let mut seq = 0;
+    loop {
+        interval.tick().await;
+        seq += 1;
+        let heartbeat = MessageWrapper::Heartbeat(Heartbeat {
+            header: Some(Header {
+                source: BACKEND_SOURCE.to_string(),
+                dest: BROADCAST_DEST.to_string(),
+                seq,
+                timestamp: Some(timesync::to_timestamp(SystemTime::now())),
+                ..Default::default()
+            }),
+            node_id: BACKEND_SOURCE.to_string(),
+            status: "running".to_string(),
+            uptime_sec: started.elapsed().as_secs() as u32,
+        });
+        if let Err(e) = state.udp_tx.send(Outbound::new(heartbeat)).await {
+            warn!("Stopping heartbeat, outbound queue closed: {}", e);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor() -> LivenessMonitor {
+        LivenessMonitor::new(&LivenessConfig {
+            stale_after_ms: 1000,
+            dead_after_ms: 3000,
+            ..Default::default()
+        })
+    }
+
+    fn heartbeat(node_id: &str, uptime_sec: u32) -> Heartbeat {
+        Heartbeat {
+            node_id: node_id.to_string(),
+            status: "ok".to_string(),
+            uptime_sec,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_alive_stale_dead_and_back() {
+        let monitor = monitor();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        let first = monitor.record(&heartbeat("motion", 1), start, SystemTime::now()).unwrap();
+        assert_eq!((first.from, first.to), (None, Liveness::Alive));
+        assert_eq!(monitor.record(&heartbeat("motion", 2), at(500), SystemTime::now()), None);
+
+        assert!(monitor.check(at(1400)).is_empty());
+        let stale = monitor.check(at(1500));
+        assert_eq!(stale[0].to, Liveness::Stale);
+        assert!(monitor.check(at(2000)).is_empty());
+        let dead = monitor.check(at(3500));
+        assert_eq!((dead[0].from, dead[0].to), (Some(Liveness::Stale), Liveness::Dead));
+
+        let back = monitor.record(&heartbeat("motion", 9), at(4000), SystemTime::now()).unwrap();
+        assert_eq!((back.from, back.to), (Some(Liveness::Dead), Liveness::Alive));
+        let nodes = monitor.list();
+        assert_eq!((nodes[0].uptime_sec, nodes[0].state), (9, Liveness::Alive));
+    }
+
+    #[test]
+    fn test_anonymous_heartbeats_are_ignored() {
+        let monitor = monitor();
+        assert_eq!(monitor.record(&heartbeat("", 1), Instant::now(), SystemTime::now()), None);
+        assert!(monitor.list().is_empty());
+    }
+}
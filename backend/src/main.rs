@@ -1,41 +1,97 @@
 mod api;
+mod cache;
+mod capture;
+mod commands;
+mod config;
+mod export;
+mod history;
+mod liveness;
+mod peers;
+mod recorder;
+mod reliable;
+mod replay;
+mod sequence;
 mod state;
+mod throttle;
+mod timesync;
 mod udp;
+mod validate;
+mod ws;
 
+use crate::config::{Cli, Command, Config, LogFormat};
 use crate::state::AppState;
+use clap::Parser;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into()),
-        ))
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    let cli = Cli::parse();
+    let config = match Config::load(&cli) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    if cli.print_config {
+        print!("{}", config.to_toml());
+        return;
+    }
+    match &cli.command {
+        Some(Command::Export(args)) => std::process::exit(export::run(&config, args)),
+        Some(Command::Import(args)) => std::process::exit(capture::run(&config, args)),
+        None => {}
+    }
+
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(&config.log.level));
+    let registry = tracing_subscriber::registry().with(filter);
+    match config.log.format {
+        LogFormat::Text => registry.with(tracing_subscriber::fmt::layer()).init(),
+        LogFormat::Json => registry.with(tracing_subscriber::fmt::layer().json()).init(),
+    }
 
     // Channel for outbound UDP
-    let (udp_tx, udp_rx) = tokio::sync::mpsc::channel(100);
+    let (udp_tx, udp_rx) = tokio::sync::mpsc::channel(config.channels.udp_outbound_capacity);
 
-    let state = AppState::new(udp_tx);
+    let state = AppState::new(config.clone(), udp_tx);
     
     // Start UDP Listener
     let udp_state = state.clone();
+    let listen_addr = config.udp.listen;
     tokio::spawn(async move {
-        if let Err(e) = udp::udp_listener(udp_state, 5000).await {
+        if let Err(e) = udp::udp_listener(udp_state, listen_addr).await {
             tracing::error!("UDP listener failed: {}", e);
         }
     });
 
     // Start UDP Sender
-    let target_addr = std::env::var("REALTIME_HOST").unwrap_or_else(|_| "127.0.0.1:5001".to_string());
+    let sender_state = state.clone();
     tokio::spawn(async move {
-        udp::udp_sender(target_addr, udp_rx).await;
+        udp::udp_sender(sender_state, udp_rx).await;
     });
 
+    // Start clock synchronisation with the Realtime peers
+    if config.timesync.interval_ms > 0 {
+        tokio::spawn(timesync::time_sync_loop(state.clone()));
+    }
+
+    // Track Realtime node heartbeats and send our own
+    tokio::spawn(liveness::liveness_loop(state.clone()));
+    if config.liveness.heartbeat_interval_ms > 0 {
+        tokio::spawn(liveness::heartbeat_loop(state.clone()));
+    }
+
+    // Record inbound traffic to disk when asked to
+    if config.recorder.autostart {
+        if let Err(e) = state.recorder.start(None, std::time::SystemTime::now()) {
+            tracing::error!("Could not start recording: {}", e);
+        }
+    }
+
     // Start Axum Server
     let app = api::app_router(state);
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    tracing::info!("Listening on 0.0.0.0:3000");
+    let listener = tokio::net::TcpListener::bind(config.http.bind).await.unwrap();
+    tracing::info!("Listening on {}", config.http.bind);
     axum::serve(listener, app).await.unwrap();
 }
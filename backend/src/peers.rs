@@ -0,0 +1,199 @@
+use crate::config::{Config, PeerConfig};
+use dashmap::DashMap;
+use serde::Serialize;
+use shared::MessageWrapper;
+use std::net::SocketAddr;
+use tracing::info;
+
+/// `Header.dest` value that fans a command out to every known peer.
+pub const BROADCAST_DEST: &str = "*";
+
+/// A Realtime node reachable over UDP.
+#[derive(Debug, Clone, Serialize)]
+pub struct Peer {
+    pub node_id: String,
+    /// `host:port`; configured peers may use hostnames.
+    pub addr: String,
+    pub actuator_prefixes: Vec<String>,
+    /// Learned from a `Heartbeat` rather than configured.
+    pub learned: bool,
+}
+
+/// Realtime nodes keyed by node id, plus the fallback `udp.realtime_host`.
+pub struct PeerTable {
+    peers: DashMap<String, Peer>,
+    default_addr: String,
+}
+
+impl PeerTable {
+    pub fn from_config(config: &Config) -> Self {
+        let peers = DashMap::new();
+        for PeerConfig { node_id, addr, actuator_prefixes } in &config.peers {
+            peers.insert(
+                node_id.clone(),
+                Peer {
+                    node_id: node_id.clone(),
+                    addr: addr.clone(),
+                    actuator_prefixes: actuator_prefixes.clone(),
+                    learned: false,
+                },
+            );
+        }
+        Self {
+            peers,
+            default_addr: config.udp.realtime_host.clone(),
+        }
+    }
+
+    /// Records where `node_id` sends from. Configured peers keep their address.
+    pub fn learn(&self, node_id: &str, addr: SocketAddr) {
+        if node_id.is_empty() {
+            return;
+        }
+        let addr = addr.to_string();
+        match self.peers.get_mut(node_id) {
+            Some(mut peer) if peer.learned && peer.addr != addr => {
+                info!("Realtime node {} moved to {}", node_id, addr);
+                peer.addr = addr;
+            }
+            Some(_) => {}
+            None => {
+                info!("Learned Realtime node {} at {}", node_id, addr);
+                self.peers.insert(
+                    node_id.to_string(),
+                    Peer {
+                        node_id: node_id.to_string(),
+                        addr,
+                        actuator_prefixes: Vec::new(),
+                        learned: true,
+                    },
+                );
+            }
+        }
+    }
+
+    pub fn list(&self) -> Vec<Peer> {
+        let mut peers: Vec<Peer> = self.peers.iter().map(|p| p.value().clone()).collect();
+        peers.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        peers
+    }
+
+    /// Destination addresses for an outbound message.
+    ///
+    /// `Header.dest` wins: `*` fans out to every peer, a node id selects that
+    /// peer. Actuator commands without a dest go to the peer with the longest
+    /// matching `actuator_prefixes` entry. Everything else goes to the default.
+    pub fn route(&self, msg: &MessageWrapper) -> Result<Vec<String>, String> {
+        let dest = msg.header().map(|h| h.dest.as_str()).unwrap_or_default();
+        if dest == BROADCAST_DEST {
+            let mut addrs: Vec<String> = self.peers.iter().map(|p| p.addr.clone()).collect();
+            addrs.push(self.default_addr.clone());
+            addrs.sort();
+            addrs.dedup();
+            return Ok(addrs);
+        }
+        if !dest.is_empty() {
+            return match self.peers.get(dest) {
+                Some(peer) => Ok(vec![peer.addr.clone()]),
+                None => Err(format!("Unknown destination node {:?}", dest)),
+            };
+        }
+        if let MessageWrapper::ActuatorCommand(cmd) = msg {
+            let best = self
+                .peers
+                .iter()
+                .filter_map(|peer| {
+                    peer.actuator_prefixes
+                        .iter()
+                        .filter(|prefix| cmd.actuator_id.starts_with(prefix.as_str()))
+                        .map(|prefix| prefix.len())
+                        .max()
+                        .map(|len| (len, peer.addr.clone()))
+                })
+                .max_by_key(|(len, _)| *len);
+            if let Some((_, addr)) = best {
+                return Ok(vec![addr]);
+            }
+        }
+        Ok(vec![self.default_addr.clone()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::proto::{actuator_command::Command, ActuatorCommand, ClockModulation, Header};
+
+    fn table() -> PeerTable {
+        let mut config = Config::default();
+        config.udp.realtime_host = "10.0.0.1:5001".to_string();
+        config.peers = vec![
+            PeerConfig {
+                node_id: "motion".to_string(),
+                addr: "10.0.0.2:5001".to_string(),
+                actuator_prefixes: vec!["joint".to_string(), "joint_wrist".to_string()],
+            },
+            PeerConfig {
+                node_id: "wrist".to_string(),
+                addr: "10.0.0.3:5001".to_string(),
+                actuator_prefixes: vec!["joint_wrist_".to_string()],
+            },
+        ];
+        PeerTable::from_config(&config)
+    }
+
+    fn clock(dest: &str) -> MessageWrapper {
+        MessageWrapper::ClockModulation(ClockModulation {
+            header: Some(Header {
+                dest: dest.to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
+
+    fn actuator(id: &str) -> MessageWrapper {
+        MessageWrapper::ActuatorCommand(ActuatorCommand {
+            actuator_id: id.to_string(),
+            command: Some(Command::Position(0.0)),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_route_by_dest() {
+        let table = table();
+        assert_eq!(table.route(&clock("wrist")).unwrap(), ["10.0.0.3:5001"]);
+        assert!(table.route(&clock("power")).is_err());
+        assert_eq!(table.route(&clock("")).unwrap(), ["10.0.0.1:5001"]);
+    }
+
+    #[test]
+    fn test_route_by_longest_actuator_prefix() {
+        let table = table();
+        assert_eq!(table.route(&actuator("joint_1")).unwrap(), ["10.0.0.2:5001"]);
+        assert_eq!(table.route(&actuator("joint_wrist_pitch")).unwrap(), ["10.0.0.3:5001"]);
+        assert_eq!(table.route(&actuator("gripper")).unwrap(), ["10.0.0.1:5001"]);
+    }
+
+    #[test]
+    fn test_broadcast_fans_out() {
+        let table = table();
+        table.learn("power", "10.0.0.4:5001".parse().unwrap());
+        assert_eq!(
+            table.route(&clock(BROADCAST_DEST)).unwrap(),
+            ["10.0.0.1:5001", "10.0.0.2:5001", "10.0.0.3:5001", "10.0.0.4:5001"]
+        );
+    }
+
+    #[test]
+    fn test_learning_does_not_override_config() {
+        let table = table();
+        table.learn("motion", "192.168.1.9:40000".parse().unwrap());
+        assert_eq!(table.route(&clock("motion")).unwrap(), ["10.0.0.2:5001"]);
+
+        table.learn("power", "10.0.0.4:5001".parse().unwrap());
+        table.learn("power", "10.0.0.5:5001".parse().unwrap());
+        assert_eq!(table.route(&clock("power")).unwrap(), ["10.0.0.5:5001"]);
+    }
+}
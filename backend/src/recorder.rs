@@ -0,0 +1,855 @@
+//! Recording of everything received from the Realtime side to disk.
+//!
+//! A recording is a directory under `recorder.dir` holding numbered files,
+//! `00000.rec`, `00001.rec`, ... Each file starts with [`FILE_MAGIC`] and is
+//! followed by length-prefixed records, little endian:
+//!
+//! ```text
+//! u32 len | i64 received_at_us | i64 latency_us | frame
+//! ```
+//!
+//! `len` counts the bytes after itself, `latency_us` is `i64::MIN` when
+//! unknown and `frame` is the datagram as received, in whichever framing the
+//! sender used. Beside each
+//! data file, `NNNNN.idx` holds `i64 received_at_us | u64 offset` entries,
+//! at most one per `index_interval_ms`, each pointing at a record start.
+
+use crate::config::RecorderConfig;
+use crate::state::Envelope;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+use thiserror::Error;
+use tracing::{error, info, warn};
+
+pub const FILE_MAGIC: &[u8; 8] = b"OPERREC1";
+/// `latency_us` of records without a latency estimate.
+pub const NO_LATENCY: i64 = i64::MIN;
+/// Bytes of a record before its frame, excluding the length prefix.
+pub const RECORD_HEADER_LEN: usize = 16;
+/// Largest frame a record can hold: the largest UDP datagram.
+pub const MAX_FRAME_LEN: usize = 65_535;
+
+#[derive(Debug, Error)]
+pub enum RecorderError {
+    #[error("Already recording to {0:?}")]
+    AlreadyRecording(String),
+    #[error("Not recording")]
+    NotRecording,
+    #[error("Invalid recording name {0:?}")]
+    InvalidName(String),
+    #[error("Recording {0:?} already exists")]
+    Exists(String),
+    #[error("Recorder I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecorderStatus {
+    pub recording: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at_us: Option<i64>,
+    pub files: u32,
+    pub records: u64,
+    pub bytes: u64,
+    /// Records left out because the writer fell behind.
+    pub dropped: u64,
+}
+
+/// An open recording and the file currently written.
+struct Recording {
+    name: String,
+    dir: PathBuf,
+    started_at_us: i64,
+    file: u32,
+    data: BufWriter<File>,
+    index: BufWriter<File>,
+    file_bytes: u64,
+    /// Reception time of the current file's first record.
+    file_started_us: Option<i64>,
+    last_indexed_us: Option<i64>,
+    records: u64,
+    bytes: u64,
+}
+
+impl Recording {
+    fn create(name: String, dir: PathBuf, started_at_us: i64) -> io::Result<Self> {
+        let (data, index) = open_file(&dir, 0)?;
+        Ok(Self {
+            name,
+            dir,
+            started_at_us,
+            file: 0,
+            data,
+            index,
+            file_bytes: FILE_MAGIC.len() as u64,
+            file_started_us: None,
+            last_indexed_us: None,
+            records: 0,
+            bytes: FILE_MAGIC.len() as u64,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.flush()?;
+        let (data, index) = open_file(&self.dir, self.file + 1)?;
+        self.file += 1;
+        self.data = data;
+        self.index = index;
+        self.file_bytes = FILE_MAGIC.len() as u64;
+        self.file_started_us = None;
+        self.last_indexed_us = None;
+        self.bytes += FILE_MAGIC.len() as u64;
+        Ok(())
+    }
+
+    fn write(&mut self, record: &Record, config: &RecorderConfig) -> io::Result<()> {
+        let at = record.received_at_us;
+        let frame = &record.frame;
+        let len = 4 + RECORD_HEADER_LEN as u64 + frame.len() as u64;
+        if let Some(file_started) = self.file_started_us {
+            let too_big = self.file_bytes + len > config.max_file_bytes;
+            let too_long = config.max_file_secs > 0
+                && at.saturating_sub(file_started) >= config.max_file_secs as i64 * 1_000_000;
+            if too_big || too_long {
+                self.rotate()?;
+            }
+        }
+
+        let interval_us = config.index_interval_ms as i64 * 1000;
+        if self.last_indexed_us.is_none_or(|last| at.saturating_sub(last) >= interval_us) {
+            self.index.write_all(&at.to_le_bytes())?;
+            self.index.write_all(&self.file_bytes.to_le_bytes())?;
+            self.last_indexed_us = Some(at);
+        }
+
+        self.data.write_all(&((RECORD_HEADER_LEN + frame.len()) as u32).to_le_bytes())?;
+        self.data.write_all(&at.to_le_bytes())?;
+        self.data.write_all(&record.latency_us.unwrap_or(NO_LATENCY).to_le_bytes())?;
+        self.data.write_all(frame)?;
+        self.file_bytes += len;
+        self.file_started_us.get_or_insert(at);
+        self.records += 1;
+        self.bytes += len;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.data.flush()?;
+        self.index.flush()
+    }
+
+    fn status(&self) -> RecorderStatus {
+        RecorderStatus {
+            recording: true,
+            name: Some(self.name.clone()),
+            path: Some(self.dir.clone()),
+            started_at_us: Some(self.started_at_us),
+            files: self.file + 1,
+            records: self.records,
+            bytes: self.bytes,
+            dropped: 0,
+        }
+    }
+
+    /// Writes queued records until the queue closes or a write fails,
+    /// flushing every `flush_interval_ms` and keeping `status` current.
+    fn run(mut self, rx: Receiver<Record>, status: Arc<Mutex<RecorderStatus>>, config: RecorderConfig) -> io::Result<()> {
+        let flush_interval = Duration::from_millis(config.flush_interval_ms);
+        let mut last_flush = Instant::now();
+        loop {
+            let result = match rx.recv_timeout(flush_interval.saturating_sub(last_flush.elapsed())) {
+                Ok(record) => self.write(&record, &config),
+                Err(RecvTimeoutError::Timeout) => Ok(()),
+                Err(RecvTimeoutError::Disconnected) => return self.flush(),
+            };
+            let result = result.and_then(|()| {
+                if last_flush.elapsed() < flush_interval {
+                    return Ok(());
+                }
+                last_flush = Instant::now();
+                let dropped = status.lock().unwrap().dropped;
+                if dropped > 0 {
+                    warn!("Recording {} has dropped {} records so far", self.name, dropped);
+                }
+                self.flush()
+            });
+            if let Err(e) = result {
+                error!("Stopped recording {}: {}", self.name, e);
+                status.lock().unwrap().recording = false;
+                return Err(e);
+            }
+            let mut status = status.lock().unwrap();
+            status.files = self.file + 1;
+            status.records = self.records;
+            status.bytes = self.bytes;
+        }
+    }
+}
+
+fn open_file(dir: &Path, file: u32) -> io::Result<(BufWriter<File>, BufWriter<File>)> {
+    let mut data = BufWriter::new(File::create(dir.join(format!("{:05}.rec", file)))?);
+    data.write_all(FILE_MAGIC)?;
+    let index = BufWriter::new(File::create(dir.join(format!("{:05}.idx", file)))?);
+    Ok((data, index))
+}
+
+/// Creates the directory of a new recording called `name` under `dir`.
+fn create_dir(dir: &Path, name: &str) -> Result<PathBuf, RecorderError> {
+    if !valid_name(name) {
+        return Err(RecorderError::InvalidName(name.to_string()));
+    }
+    let dir = dir.join(name);
+    fs::create_dir(&dir).map_err(|e| match e.kind() {
+        io::ErrorKind::AlreadyExists => RecorderError::Exists(name.to_string()),
+        _ => RecorderError::Io(e),
+    })?;
+    Ok(dir)
+}
+
+/// Writes `records` as a new recording called `name`, on the calling
+/// thread so none are dropped. A recording left incomplete by an error is
+/// removed.
+pub fn import(
+    config: &RecorderConfig,
+    name: &str,
+    records: impl IntoIterator<Item = io::Result<Record>>,
+) -> Result<RecorderStatus, RecorderError> {
+    fs::create_dir_all(&config.dir)?;
+    let dir = create_dir(&config.dir, name)?;
+    let result = write_records(config, name, &dir, records);
+    if result.is_err() {
+        let _ = fs::remove_dir_all(&dir);
+    }
+    Ok(result?)
+}
+
+fn write_records(
+    config: &RecorderConfig,
+    name: &str,
+    dir: &Path,
+    records: impl IntoIterator<Item = io::Result<Record>>,
+) -> io::Result<RecorderStatus> {
+    let mut records = records.into_iter().peekable();
+    let started_at_us = match records.peek() {
+        Some(Ok(record)) => record.received_at_us,
+        _ => 0,
+    };
+    let mut recording = Recording::create(name.to_string(), dir.to_path_buf(), started_at_us)?;
+    for record in records {
+        recording.write(&record?, config)?;
+    }
+    recording.flush()?;
+    Ok(RecorderStatus {
+        recording: false,
+        ..recording.status()
+    })
+}
+
+/// Names become directory names, so only allow a safe subset.
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 128
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Appends every inbound message to the active recording, if any.
+///
+/// Records are written by a thread per recording, fed through a bounded
+/// queue, so a slow disk never holds up the UDP listener. Records arriving
+/// while the queue is full are counted in `RecorderStatus::dropped`.
+pub struct Recorder {
+    config: RecorderConfig,
+    active: Mutex<Option<Active>>,
+}
+
+/// The recording being written and its writer thread.
+struct Active {
+    name: String,
+    queue: SyncSender<Record>,
+    status: Arc<Mutex<RecorderStatus>>,
+    writer: JoinHandle<io::Result<()>>,
+}
+
+impl Recorder {
+    pub fn new(config: RecorderConfig) -> Self {
+        Self {
+            config,
+            active: Mutex::new(None),
+        }
+    }
+
+    /// Starts a recording called `name`, or after the UTC time `now`.
+    pub fn start(&self, name: Option<&str>, now: SystemTime) -> Result<RecorderStatus, RecorderError> {
+        let mut active = self.active.lock().unwrap();
+        if let Some(recording) = active.as_ref() {
+            return Err(RecorderError::AlreadyRecording(recording.name.clone()));
+        }
+        fs::create_dir_all(&self.config.dir)?;
+        let (name, dir) = match name {
+            Some(name) => (name.to_string(), create_dir(&self.config.dir, name)?),
+            None => {
+                let base = chrono::DateTime::<chrono::Utc>::from(now).format("%Y%m%dT%H%M%SZ").to_string();
+                let mut n = 0;
+                loop {
+                    let name = if n == 0 { base.clone() } else { format!("{}-{}", base, n) };
+                    let dir = self.config.dir.join(&name);
+                    match fs::create_dir(&dir) {
+                        Ok(()) => break (name, dir),
+                        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            }
+        };
+        let recording = Recording::create(name.clone(), dir, crate::timesync::to_micros(now))?;
+        info!("Recording to {}", recording.dir.display());
+        let status = Arc::new(Mutex::new(recording.status()));
+        let (queue, rx) = mpsc::sync_channel(self.config.queue_capacity);
+        let writer = {
+            let (status, config) = (status.clone(), self.config.clone());
+            std::thread::Builder::new()
+                .name("recorder".to_string())
+                .spawn(move || recording.run(rx, status, config))?
+        };
+        let started = status.lock().unwrap().clone();
+        *active = Some(Active {
+            name,
+            queue,
+            status,
+            writer,
+        });
+        Ok(started)
+    }
+
+    /// Stops the active recording once everything queued is on disk.
+    /// Blocks while the writer catches up.
+    pub fn stop(&self) -> Result<RecorderStatus, RecorderError> {
+        let Active {
+            name,
+            queue,
+            status,
+            writer,
+        } = self.active.lock().unwrap().take().ok_or(RecorderError::NotRecording)?;
+        drop(queue);
+        match writer.join() {
+            Ok(result) => result?,
+            Err(_) => return Err(io::Error::other("recorder thread panicked").into()),
+        }
+        let status = RecorderStatus {
+            recording: false,
+            ..status.lock().unwrap().clone()
+        };
+        info!("Stopped recording {}: {} records in {} files", name, status.records, status.files);
+        Ok(status)
+    }
+
+    /// Queues `datagram`, the frame `envelope` was decoded from, to be
+    /// written exactly as it was received.
+    pub fn record(&self, envelope: &Envelope, datagram: &[u8]) {
+        let mut active = self.active.lock().unwrap();
+        let Some(recording) = active.as_ref() else {
+            return;
+        };
+        let record = Record {
+            received_at_us: envelope.meta.received_at_us,
+            latency_us: envelope.meta.latency_us,
+            frame: datagram.to_vec(),
+        };
+        match recording.queue.try_send(record) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => recording.status.lock().unwrap().dropped += 1,
+            // The writer stopped on an error it has already logged
+            Err(TrySendError::Disconnected(_)) => *active = None,
+        }
+    }
+
+    pub fn status(&self) -> RecorderStatus {
+        match self.active.lock().unwrap().as_ref() {
+            Some(recording) => recording.status.lock().unwrap().clone(),
+            None => RecorderStatus {
+                recording: false,
+                name: None,
+                path: None,
+                started_at_us: None,
+                files: 0,
+                records: 0,
+                bytes: 0,
+                dropped: 0,
+            },
+        }
+    }
+}
+
+/// A record read back from a recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub received_at_us: i64,
+    pub latency_us: Option<i64>,
+    /// The datagram as received.
+    pub frame: Vec<u8>,
+}
+
+/// Summary of a recording directory, for `GET /api/recordings`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecordingInfo {
+    pub name: String,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Recordings under `dir`, by name.
+pub fn list_recordings(dir: &Path) -> io::Result<Vec<RecordingInfo>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut recordings = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        if !entry.file_type()?.is_dir() || !valid_name(&name) {
+            continue;
+        }
+        let files = data_files(&entry.path())?;
+        if files.is_empty() {
+            continue;
+        }
+        let mut bytes = 0;
+        for file in &files {
+            bytes += fs::metadata(file)?.len();
+        }
+        recordings.push(RecordingInfo {
+            name,
+            files: files.len(),
+            bytes,
+        });
+    }
+    recordings.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(recordings)
+}
+
+/// The `.rec` files of a recording, in order.
+fn data_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "rec"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+fn read_index(path: &Path) -> io::Result<Vec<(i64, u64)>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(bytes
+        .chunks_exact(16)
+        .map(|entry| {
+            (
+                i64::from_le_bytes(entry[..8].try_into().unwrap()),
+                u64::from_le_bytes(entry[8..].try_into().unwrap()),
+            )
+        })
+        .collect())
+}
+
+/// Sequential reader over every file of a recording, seekable by time
+/// through the index files.
+pub struct RecordingReader {
+    files: Vec<PathBuf>,
+    /// Position in `files` of `current`.
+    file: usize,
+    current: Option<BufReader<File>>,
+}
+
+impl RecordingReader {
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        let files = data_files(dir)?;
+        if files.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "recording has no data files"));
+        }
+        let mut reader = Self {
+            files,
+            file: 0,
+            current: None,
+        };
+        reader.open_file(0, FILE_MAGIC.len() as u64)?;
+        Ok(reader)
+    }
+
+    fn open_file(&mut self, file: usize, offset: u64) -> io::Result<()> {
+        let mut data = BufReader::new(File::open(&self.files[file])?);
+        let mut magic = [0u8; 8];
+        data.read_exact(&mut magic)?;
+        if &magic != FILE_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a recording", self.files[file].display()),
+            ));
+        }
+        data.seek(SeekFrom::Start(offset))?;
+        self.file = file;
+        self.current = Some(data);
+        Ok(())
+    }
+
+    /// The next record, moving on to the next file at the end of one. A
+    /// record cut short, e.g. by a crash while recording, ends its file.
+    pub fn next_record(&mut self) -> io::Result<Option<Record>> {
+        loop {
+            let Some(data) = self.current.as_mut() else {
+                return Ok(None);
+            };
+            match read_record(data) {
+                Ok(Some(record)) => return Ok(Some(record)),
+                Ok(None) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {}
+                Err(e) => return Err(e),
+            }
+            if self.file + 1 == self.files.len() {
+                self.current = None;
+                return Ok(None);
+            }
+            self.open_file(self.file + 1, FILE_MAGIC.len() as u64)?;
+        }
+    }
+
+    /// Positions the reader on the first record received at or after
+    /// `at_us` and returns it, or `None` past the end.
+    pub fn seek(&mut self, at_us: i64) -> io::Result<Option<Record>> {
+        // The last file, and the last index entry in it, starting before `at_us`
+        let mut start = (0, FILE_MAGIC.len() as u64);
+        for (file, path) in self.files.iter().enumerate() {
+            let index = read_index(&path.with_extension("idx"))?;
+            match index.first() {
+                Some(&(first, _)) if first <= at_us => {}
+                Some(_) => break,
+                None => continue,
+            }
+            let entry = index.partition_point(|&(t, _)| t <= at_us) - 1;
+            start = (file, index[entry].1);
+        }
+        self.open_file(start.0, start.1)?;
+        while let Some(record) = self.next_record()? {
+            if record.received_at_us >= at_us {
+                return Ok(Some(record));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reception times of the first and last records, or `None` when the
+    /// recording is empty. Leaves the reader at the start.
+    pub fn bounds(&mut self) -> io::Result<Option<(i64, i64)>> {
+        // Only the tail after the last index entry needs to be scanned
+        let last = self
+            .files
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(file, path)| match read_index(&path.with_extension("idx")) {
+                Ok(index) => index.last().map(|&(_, offset)| Ok((file, offset))),
+                Err(e) => Some(Err(e)),
+            })
+            .transpose()?;
+        let Some((file, offset)) = last else {
+            return Ok(None);
+        };
+        self.open_file(file, offset)?;
+        let mut end = None;
+        while let Some(record) = self.next_record()? {
+            end = Some(record.received_at_us);
+        }
+        self.open_file(0, FILE_MAGIC.len() as u64)?;
+        let first = self.next_record()?;
+        self.open_file(0, FILE_MAGIC.len() as u64)?;
+        Ok(first.zip(end).map(|(first, end)| (first.received_at_us, end)))
+    }
+}
+
+/// Reads one record; `None` at a clean end of file.
+fn read_record(data: &mut impl Read) -> io::Result<Option<Record>> {
+    let mut len = [0u8; 4];
+    match data.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len < RECORD_HEADER_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "record too short"));
+    }
+    if len > RECORD_HEADER_LEN + MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("record length {} is larger than any datagram", len),
+        ));
+    }
+    let mut record = vec![0u8; len];
+    data.read_exact(&mut record)?;
+    let received_at_us = i64::from_le_bytes(record[..8].try_into().unwrap());
+    let latency_us = i64::from_le_bytes(record[8..16].try_into().unwrap());
+    Ok(Some(Record {
+        received_at_us,
+        latency_us: (latency_us != NO_LATENCY).then_some(latency_us),
+        frame: record.split_off(RECORD_HEADER_LEN),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::proto::{Header, Heartbeat};
+    use shared::MessageWrapper;
+    use std::time::UNIX_EPOCH;
+
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("recorder-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn recorder(dir: &Path, max_file_bytes: u64, max_file_secs: u64) -> Recorder {
+        Recorder::new(RecorderConfig {
+            dir: dir.to_path_buf(),
+            max_file_bytes,
+            max_file_secs,
+            ..Default::default()
+        })
+    }
+
+    fn heartbeat(seq: u64, at_ms: u64) -> Envelope {
+        let msg = MessageWrapper::Heartbeat(Heartbeat {
+            header: Some(Header {
+                seq,
+                ..Default::default()
+            }),
+            node_id: "rt".to_string(),
+            ..Default::default()
+        });
+        Envelope::received_at(msg, UNIX_EPOCH + Duration::from_millis(at_ms), Some(250))
+    }
+
+    fn record(recorder: &Recorder, envelope: Envelope) {
+        recorder.record(&envelope, &envelope.msg.to_bytes().unwrap());
+    }
+
+    /// `(received_at_us, latency_us, msg)` of every record in a data file.
+    fn read_records(path: &Path) -> Vec<(i64, i64, MessageWrapper)> {
+        let bytes = fs::read(path).unwrap();
+        assert_eq!(&bytes[..8], FILE_MAGIC);
+        let mut records = Vec::new();
+        let mut rest = &bytes[8..];
+        while !rest.is_empty() {
+            let len = u32::from_le_bytes(rest[..4].try_into().unwrap()) as usize;
+            let record = &rest[4..4 + len];
+            let at = i64::from_le_bytes(record[..8].try_into().unwrap());
+            let latency = i64::from_le_bytes(record[8..16].try_into().unwrap());
+            records.push((at, latency, MessageWrapper::from_bytes(&record[16..]).unwrap()));
+            rest = &rest[4 + len..];
+        }
+        records
+    }
+
+    fn read_index(path: &Path) -> Vec<(i64, u64)> {
+        fs::read(path)
+            .unwrap()
+            .chunks(16)
+            .map(|e| {
+                (
+                    i64::from_le_bytes(e[..8].try_into().unwrap()),
+                    u64::from_le_bytes(e[8..].try_into().unwrap()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_records_round_trip_with_index() {
+        let dir = temp_dir("round-trip");
+        let recorder = recorder(&dir, 1 << 20, 0);
+        record(&recorder, heartbeat(1, 0));
+        assert_eq!(recorder.status().records, 0);
+
+        let status = recorder.start(Some("run-1"), SystemTime::now()).unwrap();
+        assert_eq!(status.path, Some(dir.join("run-1")));
+        for (seq, at_ms) in [(1, 0), (2, 400), (3, 1000), (4, 1500), (5, 2100)] {
+            record(&recorder, heartbeat(seq, at_ms));
+        }
+        let status = recorder.stop().unwrap();
+        assert_eq!((status.recording, status.records, status.files), (false, 5, 1));
+
+        let records = read_records(&dir.join("run-1/00000.rec"));
+        assert_eq!(records.len(), 5);
+        assert_eq!((records[2].0, records[2].1), (1_000_000, 250));
+        assert_eq!(records[4].2.header().unwrap().seq, 5);
+
+        // One entry per second at most, each at a record start
+        let index = read_index(&dir.join("run-1/00000.idx"));
+        assert_eq!(index.iter().map(|e| e.0).collect::<Vec<_>>(), [0, 1_000_000, 2_100_000]);
+        assert_eq!(index[0].1, FILE_MAGIC.len() as u64);
+        let bytes = fs::read(dir.join("run-1/00000.rec")).unwrap();
+        let at = index[1].1 as usize + 4;
+        assert_eq!(i64::from_le_bytes(bytes[at..at + 8].try_into().unwrap()), 1_000_000);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotation_by_size_and_time() {
+        let dir = temp_dir("rotation");
+        let record_len = 4 + 16 + heartbeat(1, 0).msg.to_bytes().unwrap().len() as u64;
+        let recorder = recorder(&dir, 8 + 2 * record_len, 10);
+        recorder.start(Some("run"), SystemTime::now()).unwrap();
+        // Two records fit per file; the fourth starts a new file on time
+        for (seq, at_ms) in [(1, 0), (2, 1), (3, 2), (4, 10_002)] {
+            record(&recorder, heartbeat(seq, at_ms));
+        }
+        assert_eq!(recorder.stop().unwrap().files, 3);
+
+        let seqs = |file: &str| -> Vec<u64> {
+            read_records(&dir.join("run").join(file))
+                .iter()
+                .map(|r| r.2.header().unwrap().seq)
+                .collect()
+        };
+        assert_eq!(seqs("00000.rec"), [1, 2]);
+        assert_eq!(seqs("00001.rec"), [3]);
+        assert_eq!(seqs("00002.rec"), [4]);
+        assert_eq!(read_index(&dir.join("run/00001.idx")), [(2_000, 8)]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_start_and_stop_errors() {
+        let dir = temp_dir("errors");
+        let recorder = recorder(&dir, 1 << 20, 0);
+        assert!(matches!(recorder.stop(), Err(RecorderError::NotRecording)));
+        assert!(matches!(
+            recorder.start(Some("../escape"), SystemTime::now()),
+            Err(RecorderError::InvalidName(_))
+        ));
+
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let status = recorder.start(None, now).unwrap();
+        assert_eq!(status.name.as_deref(), Some("20231114T221320Z"));
+        assert!(matches!(recorder.start(None, now), Err(RecorderError::AlreadyRecording(_))));
+        recorder.stop().unwrap();
+
+        // Same second again gets a suffix; reusing a name is refused
+        let status = recorder.start(None, now).unwrap();
+        assert_eq!(status.name.as_deref(), Some("20231114T221320Z-1"));
+        recorder.stop().unwrap();
+        assert!(matches!(
+            recorder.start(Some("20231114T221320Z"), now),
+            Err(RecorderError::Exists(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reader_seeks_across_files() {
+        let dir = temp_dir("reader");
+        let record_len = 4 + 16 + heartbeat(1, 0).msg.to_bytes().unwrap().len() as u64;
+        let recorder = Recorder::new(RecorderConfig {
+            dir: dir.clone(),
+            max_file_bytes: 8 + 3 * record_len,
+            max_file_secs: 0,
+            index_interval_ms: 1,
+            ..Default::default()
+        });
+        recorder.start(Some("run"), SystemTime::now()).unwrap();
+        for seq in 1..=10 {
+            record(&recorder, heartbeat(seq, seq * 10));
+        }
+        recorder.stop().unwrap();
+        assert_eq!(list_recordings(&dir).unwrap()[0].files, 4);
+
+        let mut reader = RecordingReader::open(&dir.join("run")).unwrap();
+        assert_eq!(reader.bounds().unwrap(), Some((10_000, 100_000)));
+        let mut seqs = Vec::new();
+        while let Some(record) = reader.next_record().unwrap() {
+            assert_eq!(record.latency_us, Some(250));
+            seqs.push(MessageWrapper::from_bytes(&record.frame).unwrap().header().unwrap().seq);
+        }
+        assert_eq!(seqs, (1..=10).collect::<Vec<_>>());
+
+        // Between records, into a later file, before the start and past the end
+        assert_eq!(reader.seek(55_000).unwrap().unwrap().received_at_us, 60_000);
+        assert_eq!(reader.next_record().unwrap().unwrap().received_at_us, 70_000);
+        assert_eq!(reader.seek(0).unwrap().unwrap().received_at_us, 10_000);
+        assert_eq!(reader.seek(100_001).unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_datagrams_are_kept_verbatim() {
+        let dir = temp_dir("verbatim");
+        let recorder = recorder(&dir, 1 << 20, 0);
+        recorder.start(Some("run"), SystemTime::now()).unwrap();
+        let legacy = heartbeat(1, 0);
+        let crc = heartbeat(2, 10);
+        let datagrams = [
+            legacy.msg.to_legacy_bytes().unwrap(),
+            crc.msg.to_bytes_with_checksum(shared::Checksum::Crc32).unwrap(),
+        ];
+        recorder.record(&legacy, &datagrams[0]);
+        recorder.record(&crc, &datagrams[1]);
+        recorder.stop().unwrap();
+
+        let mut reader = RecordingReader::open(&dir.join("run")).unwrap();
+        for datagram in &datagrams {
+            assert_eq!(&reader.next_record().unwrap().unwrap().frame, datagram);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_full_queue_drops_and_counts() {
+        let dir = temp_dir("queue");
+        let recorder = Recorder::new(RecorderConfig {
+            dir: dir.clone(),
+            queue_capacity: 1,
+            ..Default::default()
+        });
+        recorder.start(Some("run"), SystemTime::now()).unwrap();
+        for seq in 1..=1000 {
+            record(&recorder, heartbeat(seq, seq));
+        }
+        let status = recorder.stop().unwrap();
+        assert_eq!(status.records + status.dropped, 1000);
+        let written = read_records(&dir.join("run/00000.rec"));
+        assert_eq!(written.len() as u64, status.records);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_oversized_record_length_is_corruption() {
+        let dir = temp_dir("oversized");
+        fs::create_dir_all(&dir).unwrap();
+        let mut bytes = FILE_MAGIC.to_vec();
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&[0; 16]);
+        fs::write(dir.join("00000.rec"), bytes).unwrap();
+
+        let mut reader = RecordingReader::open(&dir).unwrap();
+        let err = reader.next_record().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+use crate::config::ReliabilityConfig;
+use dashmap::DashMap;
+use serde::Serialize;
+use shared::proto::{Ack, Reliability};
+use shared::ws::DeliveryStatus;
+use shared::{MessageKind, MessageWrapper};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::time::Instant;
+use tracing::warn;
+
+/// Final result of a tracked command, handed back to whoever sent it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Delivery {
+    pub kind: MessageKind,
+    pub seq: u64,
+    pub attempts: u32,
+    #[serde(flatten)]
+    pub status: DeliveryStatus,
+}
+
+/// A frame that needs to go out again.
+pub struct Retransmit {
+    pub data: Vec<u8>,
+    pub targets: Vec<String>,
+}
+
+struct Pending {
+    kind: MessageKind,
+    data: Vec<u8>,
+    targets: Vec<String>,
+    attempts: u32,
+    backoff: Duration,
+    next_retry: Instant,
+    deadline: Instant,
+    reply: Option<oneshot::Sender<Delivery>>,
+}
+
+/// Tracks `RELIABLE` commands until the Realtime side acks them by `seq`,
+/// retransmitting with exponential backoff until the delivery deadline.
+pub struct DeliveryTracker {
+    config: ReliabilityConfig,
+    next_seq: AtomicU64,
+    pending: DashMap<u64, Pending>,
+}
+
+pub fn is_reliable(msg: &MessageWrapper) -> bool {
+    msg.qos().is_some_and(|qos| qos.reliability() == Reliability::Reliable)
+}
+
+impl DeliveryTracker {
+    pub fn new(config: ReliabilityConfig) -> Self {
+        Self {
+            config,
+            next_seq: AtomicU64::new(1),
+            pending: DashMap::new(),
+        }
+    }
+
+    /// Next backend-owned command sequence number.
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Stamps a backend-owned sequence number on a reliable message so the
+    /// matching `Ack.seq` can be recognised. Returns `None` for best effort.
+    pub fn assign_seq(&self, msg: &mut MessageWrapper) -> Option<u64> {
+        if !is_reliable(msg) {
+            return None;
+        }
+        let seq = self.next_seq();
+        msg.header_mut()?.seq = seq;
+        Some(seq)
+    }
+
+    /// Starts tracking a frame that has just been sent for the first time.
+    pub fn track(
+        &self,
+        seq: u64,
+        kind: MessageKind,
+        data: Vec<u8>,
+        targets: Vec<String>,
+        reply: Option<oneshot::Sender<Delivery>>,
+        now: Instant,
+    ) {
+        let backoff = Duration::from_millis(self.config.initial_backoff_ms);
+        self.pending.insert(
+            seq,
+            Pending {
+                kind,
+                data,
+                targets,
+                attempts: 1,
+                backoff,
+                next_retry: now + backoff,
+                deadline: now + Duration::from_millis(self.config.deadline_ms),
+                reply,
+            },
+        );
+    }
+
+    /// Resolves the pending command acknowledged by `ack`. Returns false for
+    /// acks that match nothing (late, duplicate or not ours).
+    pub fn ack(&self, ack: &Ack) -> bool {
+        let Some((seq, pending)) = self.pending.remove(&ack.seq) else {
+            return false;
+        };
+        let status = if ack.ok {
+            DeliveryStatus::Acked {
+                message: ack.message.clone(),
+            }
+        } else {
+            DeliveryStatus::Rejected {
+                message: ack.message.clone(),
+            }
+        };
+        Self::resolve(seq, pending, status);
+        true
+    }
+
+    /// Frames whose retry time has come. Commands past their deadline are
+    /// resolved as timed out instead.
+    pub fn due(&self, now: Instant) -> Vec<Retransmit> {
+        let expired: Vec<u64> = self
+            .pending
+            .iter()
+            .filter(|p| now >= p.deadline)
+            .map(|p| *p.key())
+            .collect();
+        for seq in expired {
+            if let Some((seq, pending)) = self.pending.remove(&seq) {
+                warn!(
+                    "{} seq {} not acknowledged after {} attempts",
+                    pending.kind, seq, pending.attempts
+                );
+                Self::resolve(seq, pending, DeliveryStatus::TimedOut);
+            }
+        }
+
+        let max_backoff = Duration::from_millis(self.config.max_backoff_ms);
+        let mut resend = Vec::new();
+        for mut pending in self.pending.iter_mut() {
+            if now >= pending.next_retry {
+                pending.attempts += 1;
+                pending.backoff = (pending.backoff * 2).min(max_backoff);
+                pending.next_retry = now + pending.backoff;
+                resend.push(Retransmit {
+                    data: pending.data.clone(),
+                    targets: pending.targets.clone(),
+                });
+            }
+        }
+        resend
+    }
+
+    #[cfg(test)]
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn resolve(seq: u64, pending: Pending, status: DeliveryStatus) {
+        if let Some(reply) = pending.reply {
+            let _ = reply.send(Delivery {
+                kind: pending.kind,
+                seq,
+                attempts: pending.attempts,
+                status,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::proto::{ActuatorCommand, Header, QosProfile};
+
+    fn config() -> ReliabilityConfig {
+        ReliabilityConfig {
+            initial_backoff_ms: 100,
+            max_backoff_ms: 300,
+            deadline_ms: 1000,
+            retry_tick_ms: 10,
+        }
+    }
+
+    fn command(reliability: Reliability) -> MessageWrapper {
+        MessageWrapper::ActuatorCommand(ActuatorCommand {
+            header: Some(Header {
+                qos: Some(QosProfile {
+                    reliability: reliability as i32,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_only_reliable_messages_get_a_seq() {
+        let tracker = DeliveryTracker::new(config());
+        let mut best_effort = command(Reliability::BestEffort);
+        assert_eq!(tracker.assign_seq(&mut best_effort), None);
+
+        let mut reliable = command(Reliability::Reliable);
+        assert_eq!(tracker.assign_seq(&mut reliable), Some(1));
+        assert_eq!(reliable.header().unwrap().seq, 1);
+        let mut reliable = command(Reliability::Reliable);
+        assert_eq!(tracker.assign_seq(&mut reliable), Some(2));
+    }
+
+    #[test]
+    fn test_ack_resolves_pending() {
+        let tracker = DeliveryTracker::new(config());
+        let (tx, mut rx) = oneshot::channel();
+        tracker.track(7, MessageKind::ActuatorCommand, vec![1], vec![], Some(tx), Instant::now());
+
+        assert!(!tracker.ack(&Ack { ok: true, message: String::new(), seq: 8 }));
+        assert!(tracker.ack(&Ack { ok: false, message: "limit".to_string(), seq: 7 }));
+        assert_eq!(tracker.pending_count(), 0);
+
+        let delivery = rx.try_recv().unwrap();
+        assert_eq!(delivery.seq, 7);
+        assert_eq!(delivery.status, DeliveryStatus::Rejected { message: "limit".to_string() });
+    }
+
+    #[test]
+    fn test_backoff_and_deadline() {
+        let tracker = DeliveryTracker::new(config());
+        let (tx, mut rx) = oneshot::channel();
+        let start = Instant::now();
+        tracker.track(1, MessageKind::ActuatorCommand, vec![1], vec![], Some(tx), start);
+
+        let at = |ms| start + Duration::from_millis(ms);
+        assert!(tracker.due(at(50)).is_empty());
+        assert_eq!(tracker.due(at(100)).len(), 1); // next retry after 200 ms
+        assert!(tracker.due(at(250)).is_empty());
+        assert_eq!(tracker.due(at(300)).len(), 1); // backoff capped at 300 ms
+        assert!(tracker.due(at(550)).is_empty());
+        assert_eq!(tracker.due(at(600)).len(), 1);
+
+        assert!(tracker.due(at(1000)).is_empty());
+        let delivery = rx.try_recv().unwrap();
+        assert_eq!(delivery.status, DeliveryStatus::TimedOut);
+        assert_eq!(delivery.attempts, 4);
+    }
+}
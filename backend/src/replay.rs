@@ -0,0 +1,668 @@
+//! Playback of recorded sessions into the live feed.
+//!
+//! Records are fed through the same ingest path as live traffic at their
+//! recorded pace, scaled by the speed factor, so the latest-value cache,
+//! history and WebSocket clients see them as if they were live.
+//! With a target, every recorded datagram is also sent over UDP exactly as
+//! it was received, to regression-test a Realtime node against a captured
+//! session. Targets are limited to configured peers and `udp.realtime_host`.
+
+use crate::recorder::{self, Record, RecordingReader};
+use crate::state::{AppState, Envelope};
+use crate::udp;
+use shared::ws::{ReplayControl, ReplayState, ReplayStatus, ServerEvent};
+use shared::MessageWrapper;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// Upper bound for the speed factor.
+const MAX_SPEED: f64 = 1000.0;
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("No recording named {0:?}")]
+    NotFound(String),
+    #[error("Recording {0:?} is empty")]
+    Empty(String),
+    #[error("Nothing is being replayed")]
+    NotReplaying,
+    #[error("Speed must be greater than 0 and at most {MAX_SPEED}, got {0}")]
+    InvalidSpeed(f64),
+    #[error("Invalid replay target {0:?}: {1}")]
+    Target(String, String),
+    #[error("Replay I/O error: {0}")]
+    Io(#[from] io::Error),
+}
+
+type Request = (ReplayControl, oneshot::Sender<Result<ReplayStatus, ReplayError>>);
+
+struct Session {
+    requests: mpsc::Sender<Request>,
+    status: Arc<Mutex<ReplayStatus>>,
+}
+
+/// Runs at most one replay at a time and forwards control requests to it.
+#[derive(Default)]
+pub struct Replayer {
+    /// Held across a request so requests apply one at a time.
+    session: tokio::sync::Mutex<Option<Session>>,
+    /// Status of the current or last replay, readable while a request runs.
+    status: Mutex<Option<Arc<Mutex<ReplayStatus>>>>,
+}
+
+impl Replayer {
+    pub fn status(&self) -> Option<ReplayStatus> {
+        let status = self.status.lock().unwrap();
+        status.as_ref().map(|status| status.lock().unwrap().clone())
+    }
+
+    /// Applies `control` and tells every WebSocket client about the result.
+    pub async fn control(&self, state: &AppState, control: ReplayControl) -> Result<ReplayStatus, ReplayError> {
+        let mut session = self.session.lock().await;
+        let status = match control {
+            ReplayControl::Start {
+                name,
+                speed,
+                looping,
+                target,
+                paused,
+            } => {
+                let started = start(state, name, speed, looping, target, paused).await?;
+                let status = started.status.lock().unwrap().clone();
+                *self.status.lock().unwrap() = Some(started.status.clone());
+                // Dropping the previous session's sender ends its player
+                *session = Some(started);
+                status
+            }
+            control => {
+                let current = session.as_ref().ok_or(ReplayError::NotReplaying)?;
+                let (reply_tx, reply_rx) = oneshot::channel();
+                if current.requests.send((control, reply_tx)).await.is_err() {
+                    return Err(ReplayError::NotReplaying);
+                }
+                reply_rx.await.map_err(|_| ReplayError::NotReplaying)??
+            }
+        };
+        let _ = state.events.send(ServerEvent::Replay(status.clone()));
+        Ok(status)
+    }
+}
+
+/// The address for `target` if it names a configured peer, by node id or
+/// address, or is the realtime host.
+fn configured_target<'a>(state: &'a AppState, target: &str) -> Option<&'a str> {
+    let config = &state.config;
+    if target == config.udp.realtime_host {
+        return Some(&config.udp.realtime_host);
+    }
+    config
+        .peers
+        .iter()
+        .find(|peer| peer.node_id == target || peer.addr == target)
+        .map(|peer| peer.addr.as_str())
+}
+
+fn check_speed(speed: f64) -> Result<(), ReplayError> {
+    if !speed.is_finite() || speed <= 0.0 || speed > MAX_SPEED {
+        return Err(ReplayError::InvalidSpeed(speed));
+    }
+    Ok(())
+}
+
+async fn start(
+    state: &AppState,
+    name: String,
+    speed: f64,
+    looping: bool,
+    target: Option<String>,
+    paused: bool,
+) -> Result<Session, ReplayError> {
+    check_speed(speed)?;
+    if !recorder::valid_name(&name) {
+        return Err(ReplayError::NotFound(name));
+    }
+    let dir = state.config.recorder.dir.join(&name);
+    let opened = {
+        let name = name.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut reader = RecordingReader::open(&dir).map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => ReplayError::NotFound(name.clone()),
+                _ => ReplayError::Io(e),
+            })?;
+            let bounds = reader.bounds()?.ok_or(ReplayError::Empty(name))?;
+            let next = reader.next_record()?;
+            Ok::<_, ReplayError>((reader, bounds, next))
+        })
+        .await
+        .map_err(io::Error::other)?
+    };
+    let (reader, (start_us, end_us), next) = opened?;
+
+    let udp = match &target {
+        Some(target) => {
+            let host = configured_target(state, target)
+                .ok_or_else(|| ReplayError::Target(target.clone(), "not a configured peer".to_string()))?;
+            let addr = tokio::net::lookup_host(host)
+                .await
+                .map_err(|e| ReplayError::Target(target.clone(), e.to_string()))?
+                .next()
+                .ok_or_else(|| ReplayError::Target(target.clone(), "no address".to_string()))?;
+            let bind: SocketAddr = if addr.is_ipv4() {
+                ([0, 0, 0, 0], 0).into()
+            } else {
+                ([0u16; 8], 0).into()
+            };
+            Some((UdpSocket::bind(bind).await?, addr))
+        }
+        None => None,
+    };
+
+    info!("Replaying {} at {}x{}", name, speed, if looping { ", looping" } else { "" });
+    let status = Arc::new(Mutex::new(ReplayStatus {
+        name,
+        state: if paused { ReplayState::Paused } else { ReplayState::Playing },
+        position_us: start_us,
+        start_us,
+        end_us,
+        speed,
+        looping,
+        target,
+        records_played: 0,
+    }));
+    let (requests, rx) = mpsc::channel(8);
+    let player = Player {
+        state: state.clone(),
+        reader: Arc::new(Mutex::new(reader)),
+        status: status.clone(),
+        udp,
+        next,
+        clock: Clock::new(start_us, speed),
+    };
+    tokio::spawn(player.run(rx));
+    Ok(Session { requests, status })
+}
+
+/// Maps recording time onto the wall clock at the current speed.
+struct Clock {
+    wall: Instant,
+    position_us: i64,
+    speed: f64,
+}
+
+impl Clock {
+    fn new(position_us: i64, speed: f64) -> Self {
+        Self {
+            wall: Instant::now(),
+            position_us,
+            speed,
+        }
+    }
+
+    /// When the record received at `at_us` is due.
+    fn due(&self, at_us: i64) -> Instant {
+        let ahead = (at_us - self.position_us).max(0) as f64 / self.speed;
+        self.wall + Duration::from_micros(ahead as u64)
+    }
+}
+
+/// Runs `read` on the blocking pool, keeping recording I/O off the runtime.
+async fn read_blocking<T: Send + 'static>(
+    reader: &Arc<Mutex<RecordingReader>>,
+    read: impl FnOnce(&mut RecordingReader) -> io::Result<T> + Send + 'static,
+) -> io::Result<T> {
+    let reader = reader.clone();
+    tokio::task::spawn_blocking(move || read(&mut reader.lock().unwrap()))
+        .await
+        .map_err(io::Error::other)?
+}
+
+struct Player {
+    state: AppState,
+    reader: Arc<Mutex<RecordingReader>>,
+    status: Arc<Mutex<ReplayStatus>>,
+    udp: Option<(UdpSocket, SocketAddr)>,
+    /// The record to play next; `None` at the end.
+    next: Option<Record>,
+    clock: Clock,
+}
+
+impl Player {
+    async fn run(mut self, mut requests: mpsc::Receiver<Request>) {
+        loop {
+            let playing = self.status.lock().unwrap().state == ReplayState::Playing;
+            let due = match (&self.next, playing) {
+                (Some(record), true) => Some(self.clock.due(record.received_at_us)),
+                _ => None,
+            };
+            tokio::select! {
+                request = requests.recv() => {
+                    let Some((control, reply)) = request else {
+                        return;
+                    };
+                    let stop = control == ReplayControl::Stop;
+                    let _ = reply.send(self.apply(control).await);
+                    if stop {
+                        return;
+                    }
+                }
+                _ = tokio::time::sleep_until(due.unwrap_or_else(Instant::now)), if due.is_some() => {
+                    if let Err(e) = self.play_next().await {
+                        warn!("Replay stopped: {}", e);
+                        self.finish(ReplayState::Stopped);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn apply(&mut self, control: ReplayControl) -> Result<ReplayStatus, ReplayError> {
+        let mut status = self.status.lock().unwrap().clone();
+        match control {
+            // Handled by the `Replayer`
+            ReplayControl::Start { .. } => {}
+            ReplayControl::Play => {
+                if self.next.is_none() {
+                    // Play again from the start once finished
+                    let start_us = status.start_us;
+                    self.next = read_blocking(&self.reader, move |reader| reader.seek(start_us)).await?;
+                    status.position_us = status.start_us;
+                }
+                status.state = ReplayState::Playing;
+                self.clock = Clock::new(status.position_us, status.speed);
+            }
+            ReplayControl::Pause => {
+                if status.state == ReplayState::Playing {
+                    status.state = ReplayState::Paused;
+                }
+            }
+            ReplayControl::Seek { position_us } => {
+                let position_us = position_us.clamp(status.start_us, status.end_us);
+                self.next = read_blocking(&self.reader, move |reader| reader.seek(position_us)).await?;
+                status.position_us = position_us;
+                if status.state == ReplayState::Finished {
+                    status.state = ReplayState::Paused;
+                }
+                self.clock = Clock::new(position_us, status.speed);
+            }
+            ReplayControl::Speed { factor } => {
+                check_speed(factor)?;
+                status.speed = factor;
+                self.clock = Clock::new(status.position_us, factor);
+            }
+            ReplayControl::Loop { enabled } => status.looping = enabled,
+            ReplayControl::Stop => status.state = ReplayState::Stopped,
+        }
+        *self.status.lock().unwrap() = status.clone();
+        Ok(status)
+    }
+
+    async fn play_next(&mut self) -> io::Result<()> {
+        let Some(record) = self.next.take() else {
+            return Ok(());
+        };
+        match MessageWrapper::from_bytes(&record.frame) {
+            Ok(msg) => {
+                let mut envelope = Envelope::received_at(msg, SystemTime::now(), record.latency_us);
+                envelope.meta.replayed = true;
+                udp::ingest(&self.state, envelope);
+            }
+            Err(e) => warn!("Skipping undecodable recorded frame: {}", e),
+        }
+        if let Some((socket, target)) = &self.udp {
+            match socket.send_to(&record.frame, target).await {
+                Ok(_) => {
+                    if let (Some(capture), Ok(local)) = (&self.state.capture, socket.local_addr()) {
+                        capture.record(local, *target, &record.frame, SystemTime::now());
+                    }
+                }
+                Err(e) => warn!("Failed to re-emit recorded frame to {}: {}", target, e),
+            }
+        }
+        {
+            let mut status = self.status.lock().unwrap();
+            status.position_us = record.received_at_us;
+            status.records_played += 1;
+        }
+
+        self.next = read_blocking(&self.reader, |reader| reader.next_record()).await?;
+        if self.next.is_none() {
+            let (looping, start_us, speed) = {
+                let status = self.status.lock().unwrap();
+                (status.looping, status.start_us, status.speed)
+            };
+            if looping {
+                self.next = read_blocking(&self.reader, move |reader| reader.seek(start_us)).await?;
+                self.clock = Clock::new(start_us, speed);
+            } else {
+                self.finish(ReplayState::Finished);
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, state: ReplayState) {
+        self.next = None;
+        let status = {
+            let mut status = self.status.lock().unwrap();
+            status.state = state;
+            status.clone()
+        };
+        info!("Replay of {} {:?}", status.name, state);
+        let _ = self.state.events.send(ServerEvent::Replay(status));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::Capture;
+    use crate::config::{CaptureConfig, Config, PeerConfig};
+    use crate::recorder::Recorder;
+    use shared::proto::actuator_command::Command;
+    use shared::proto::{ActuatorCommand, FaultInjection, Header, Heartbeat, TimeSync};
+    use std::time::UNIX_EPOCH;
+
+    /// A state whose recording directory holds `name` with `count` heartbeats
+    /// received 10 ms apart.
+    fn recorded(test: &str, name: &str, count: u64) -> AppState {
+        let frames = (1..=count).map(|seq| {
+            let msg = MessageWrapper::Heartbeat(Heartbeat {
+                header: Some(Header {
+                    seq,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+            let frame = msg.to_bytes().unwrap();
+            (msg, frame)
+        });
+        recorded_frames(test, name, Config::default(), frames)
+    }
+
+    /// A state using `config` whose recording directory holds `name` with
+    /// `frames` received 10 ms apart, each stored as the given datagram.
+    fn recorded_frames(
+        test: &str,
+        name: &str,
+        mut config: Config,
+        frames: impl IntoIterator<Item = (MessageWrapper, Vec<u8>)>,
+    ) -> AppState {
+        let dir = std::env::temp_dir().join(format!("replay-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        config.recorder.dir = dir;
+        let recorder = Recorder::new(config.recorder.clone());
+        recorder.start(Some(name), SystemTime::now()).unwrap();
+        for (i, (msg, frame)) in (1..).zip(frames) {
+            let at = UNIX_EPOCH + Duration::from_millis(1_000_000 + i * 10);
+            recorder.record(&Envelope::received_at(msg, at, None), &frame);
+        }
+        recorder.stop().unwrap();
+        let (udp_tx, _udp_rx) = mpsc::channel(10);
+        AppState::new(config, udp_tx)
+    }
+
+    async fn next_seq(rx: &mut tokio::sync::broadcast::Receiver<Envelope>) -> u64 {
+        let envelope = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await.unwrap().unwrap();
+        envelope.msg.header().unwrap().seq
+    }
+
+    fn start(name: &str, looping: bool, paused: bool) -> ReplayControl {
+        ReplayControl::Start {
+            name: name.to_string(),
+            speed: 10.0,
+            looping,
+            target: None,
+            paused,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plays_into_broadcast_and_finishes() {
+        let state = recorded("finish", "run", 3);
+        let mut rx = state.tx.subscribe();
+        let mut events = state.events.subscribe();
+        let replayer = Replayer::default();
+        let status = replayer.control(&state, start("run", false, false)).await.unwrap();
+        assert_eq!((status.start_us, status.end_us), (1_000_010_000, 1_000_030_000));
+
+        for seq in 1..=3 {
+            assert_eq!(next_seq(&mut rx).await, seq);
+        }
+        // Started, then finished
+        assert!(matches!(events.recv().await.unwrap(), ServerEvent::Replay(_)));
+        match tokio::time::timeout(Duration::from_secs(1), events.recv()).await.unwrap().unwrap() {
+            ServerEvent::Replay(status) => {
+                assert_eq!(status.state, ReplayState::Finished);
+                assert_eq!(status.records_played, 3);
+            }
+            other => panic!("Unexpected event: {:?}", other),
+        }
+
+        // Late joiners and history readers see the replayed state too
+        assert_eq!(state.history.query(None, None, None).len(), 3);
+        let latest = state.latest_values.latest();
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].header().unwrap().seq, 3);
+        assert!(state.history.query(None, None, None).iter().all(|e| e.meta.replayed));
+    }
+
+    #[tokio::test]
+    async fn test_looping_leaves_link_stats_alone() {
+        let state = recorded("stats", "run", 3);
+        // The recorded source is also live, and ahead of the recording
+        for seq in 1..=5 {
+            let msg = MessageWrapper::Heartbeat(Heartbeat {
+                header: Some(Header {
+                    seq,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+            udp::ingest(&state, Envelope::new(msg));
+        }
+        let live = state.sequences.stats();
+
+        let mut rx = state.tx.subscribe();
+        let replayer = Replayer::default();
+        replayer.control(&state, start("run", true, false)).await.unwrap();
+        let mut seqs = Vec::new();
+        for _ in 0..7 {
+            seqs.push(next_seq(&mut rx).await);
+        }
+        assert_eq!(seqs, [1, 2, 3, 1, 2, 3, 1]);
+        replayer.control(&state, ReplayControl::Stop).await.unwrap();
+        assert_eq!(state.sequences.stats(), live);
+        assert_eq!((live[0].received, live[0].resets, live[0].duplicates), (5, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_pause_seek_and_loop() {
+        let state = recorded("controls", "run", 5);
+        let mut rx = state.tx.subscribe();
+        let replayer = Replayer::default();
+        replayer.control(&state, start("run", true, true)).await.unwrap();
+
+        let status = replayer
+            .control(&state, ReplayControl::Seek { position_us: 1_000_035_000 })
+            .await
+            .unwrap();
+        assert_eq!((status.state, status.position_us), (ReplayState::Paused, 1_000_035_000));
+        assert!(rx.try_recv().is_err());
+
+        replayer.control(&state, ReplayControl::Play).await.unwrap();
+        // 4 and 5, then around again from the start
+        let mut seqs = Vec::new();
+        for _ in 0..4 {
+            seqs.push(next_seq(&mut rx).await);
+        }
+        assert_eq!(seqs, [4, 5, 1, 2]);
+
+        let status = replayer.control(&state, ReplayControl::Stop).await.unwrap();
+        assert_eq!(status.state, ReplayState::Stopped);
+        assert!(matches!(
+            replayer.control(&state, ReplayControl::Play).await,
+            Err(ReplayError::NotReplaying)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reemits_udp_frames() {
+        let header = |seq| {
+            Some(Header {
+                seq,
+                ..Default::default()
+            })
+        };
+        let time_sync = MessageWrapper::TimeSync(TimeSync {
+            header: header(1),
+            ..Default::default()
+        });
+        let heartbeat = MessageWrapper::Heartbeat(Heartbeat {
+            header: header(2),
+            ..Default::default()
+        });
+        let actuator = MessageWrapper::ActuatorCommand(ActuatorCommand {
+            header: header(3),
+            actuator_id: "joint_1".to_string(),
+            command: Some(Command::Position(1.0)),
+            ..Default::default()
+        });
+        let fault = MessageWrapper::FaultInjection(FaultInjection {
+            header: header(4),
+            ..Default::default()
+        });
+        // Every datagram is re-emitted byte for byte, whatever its framing
+        // and whether or not a client could have sent it
+        let datagrams = [
+            time_sync.to_legacy_bytes().unwrap(),
+            heartbeat.to_bytes().unwrap(),
+            actuator.to_bytes_with_checksum(shared::Checksum::Crc16).unwrap(),
+            fault.to_bytes().unwrap(),
+        ];
+        let frames = [
+            (time_sync, datagrams[0].clone()),
+            (heartbeat, datagrams[1].clone()),
+            (actuator, datagrams[2].clone()),
+            (fault, datagrams[3].clone()),
+        ];
+        let target = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut config = Config::default();
+        config.peers.push(PeerConfig {
+            node_id: "sim".to_string(),
+            addr: target.local_addr().unwrap().to_string(),
+            actuator_prefixes: Vec::new(),
+        });
+        let mut state = recorded_frames("udp", "run", config, frames);
+        let path = std::env::temp_dir().join(format!("replay-capture-{}.pcap", std::process::id()));
+        state.capture = Some(Arc::new(Capture::create(&path, &CaptureConfig::default()).unwrap()));
+        let replayer = Replayer::default();
+        let control = ReplayControl::Start {
+            name: "run".to_string(),
+            speed: 10.0,
+            looping: false,
+            target: Some("sim".to_string()),
+            paused: false,
+        };
+        replayer.control(&state, control).await.unwrap();
+
+        let mut buf = [0u8; 2048];
+        for datagram in &datagrams {
+            let (len, _) = tokio::time::timeout(Duration::from_secs(1), target.recv_from(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(&buf[..len], &datagram[..]);
+        }
+        assert_eq!(replayer.status().unwrap().records_played, 4);
+
+        // Re-emitted frames are captured like any other outbound datagram
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        state.capture.as_ref().unwrap().flush();
+        let bytes = std::fs::read(&path).unwrap();
+        let first_len = u32::from_le_bytes(bytes[32..36].try_into().unwrap()) as usize;
+        let packet = &bytes[40..40 + first_len];
+        assert_eq!(&packet[22..24], &target.local_addr().unwrap().port().to_be_bytes());
+        assert_eq!(&packet[28..], &datagrams[0][..]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reemits_session_recorded_by_listener() {
+        let dir = std::env::temp_dir().join(format!("replay-listener-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let target = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut config = Config::default();
+        config.recorder.dir = dir.clone();
+        config.udp.realtime_host = target.local_addr().unwrap().to_string();
+        let (udp_tx, _udp_rx) = mpsc::channel(10);
+        let state = AppState::new(config, udp_tx);
+        let port = 5559;
+        tokio::spawn(udp::udp_listener(state.clone(), ([127, 0, 0, 1], port).into()));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Telemetry from a Realtime node, recorded as it arrives
+        state.recorder.start(Some("live"), SystemTime::now()).unwrap();
+        let node = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut sent = Vec::new();
+        for seq in 1..=3 {
+            let msg = MessageWrapper::Heartbeat(Heartbeat {
+                header: Some(Header {
+                    source: "rt".to_string(),
+                    seq,
+                    ..Default::default()
+                }),
+                node_id: "rt".to_string(),
+                ..Default::default()
+            });
+            let datagram = msg.to_bytes().unwrap();
+            node.send_to(&datagram, ("127.0.0.1", port)).await.unwrap();
+            sent.push(datagram);
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        state.recorder.stop().unwrap();
+
+        let replayer = Replayer::default();
+        let mut control = start("live", false, false);
+        if let ReplayControl::Start { target: replay_target, .. } = &mut control {
+            *replay_target = Some(state.config.udp.realtime_host.clone());
+        }
+        replayer.control(&state, control).await.unwrap();
+        let mut buf = [0u8; 2048];
+        for datagram in &sent {
+            let (len, _) = tokio::time::timeout(Duration::from_secs(1), target.recv_from(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(&buf[..len], &datagram[..]);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_start_errors() {
+        let state = recorded("errors", "run", 1);
+        let replayer = Replayer::default();
+        assert!(matches!(
+            replayer.control(&state, start("missing", false, false)).await,
+            Err(ReplayError::NotFound(_))
+        ));
+        let mut fast = start("run", false, false);
+        if let ReplayControl::Start { speed, .. } = &mut fast {
+            *speed = 0.0;
+        }
+        assert!(matches!(replayer.control(&state, fast).await, Err(ReplayError::InvalidSpeed(_))));
+        let mut elsewhere = start("run", false, false);
+        if let ReplayControl::Start { target, .. } = &mut elsewhere {
+            *target = Some("10.1.2.3:5001".to_string());
+        }
+        assert!(matches!(replayer.control(&state, elsewhere).await, Err(ReplayError::Target(..))));
+    }
+}
@@ -0,0 +1,203 @@
+use dashmap::DashMap;
+use shared::ws::StreamStats;
+use shared::{MessageKind, MessageWrapper};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Sequence numbers this far behind the newest one are treated as a restart
+/// of the sender rather than a late packet.
+const WINDOW: u64 = 64;
+
+/// What a single sequence number said about its stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeqEvent {
+    First,
+    InOrder,
+    Gap { missing: u64 },
+    Duplicate,
+    OutOfOrder,
+    Reset,
+}
+
+struct Stream {
+    stats: StreamStats,
+    /// Bit `i` is set when `last_seq - i` has been received.
+    seen: u64,
+}
+
+/// Tracks `Header.seq` per `(source, kind)` to tell link loss apart from a
+/// Realtime node restarting.
+#[derive(Default)]
+pub struct SequenceTracker {
+    streams: DashMap<(String, MessageKind), Stream>,
+    generation: AtomicU64,
+}
+
+impl SequenceTracker {
+    /// Accounts for an inbound message. Messages without a sequence number
+    /// (no header, or `seq == 0`) are not tracked.
+    pub fn observe(&self, msg: &MessageWrapper) -> Option<SeqEvent> {
+        let seq = msg.header().map(|h| h.seq).filter(|seq| *seq > 0)?;
+        let kind = msg.kind();
+        let key = (msg.source().to_string(), kind);
+        self.generation.fetch_add(1, Ordering::Relaxed);
+
+        let mut stream = match self.streams.get_mut(&key) {
+            Some(stream) => stream,
+            None => {
+                let stats = StreamStats {
+                    source: key.0.clone(),
+                    kind,
+                    last_seq: seq,
+                    received: 1,
+                    gaps: 0,
+                    lost: 0,
+                    duplicates: 0,
+                    out_of_order: 0,
+                    resets: 0,
+                };
+                self.streams.insert(key, Stream { stats, seen: 1 });
+                return Some(SeqEvent::First);
+            }
+        };
+        let stream = &mut *stream;
+        stream.stats.received += 1;
+        let last = stream.stats.last_seq;
+
+        let event = if seq > last {
+            let ahead = seq - last;
+            stream.seen = if ahead >= WINDOW { 0 } else { stream.seen << ahead };
+            stream.seen |= 1;
+            stream.stats.last_seq = seq;
+            if ahead == 1 {
+                SeqEvent::InOrder
+            } else {
+                stream.stats.gaps += 1;
+                stream.stats.lost += ahead - 1;
+                SeqEvent::Gap { missing: ahead - 1 }
+            }
+        } else if last - seq >= WINDOW {
+            stream.stats.resets += 1;
+            stream.stats.last_seq = seq;
+            stream.seen = 1;
+            SeqEvent::Reset
+        } else {
+            let bit = 1 << (last - seq);
+            if stream.seen & bit != 0 {
+                stream.stats.duplicates += 1;
+                SeqEvent::Duplicate
+            } else {
+                stream.seen |= bit;
+                stream.stats.out_of_order += 1;
+                stream.stats.lost = stream.stats.lost.saturating_sub(1);
+                SeqEvent::OutOfOrder
+            }
+        };
+        Some(event)
+    }
+
+    /// Per-stream statistics, sorted by source then kind.
+    pub fn stats(&self) -> Vec<StreamStats> {
+        let mut stats: Vec<StreamStats> = self.streams.iter().map(|s| s.stats.clone()).collect();
+        stats.sort_by(|a, b| (&a.source, a.kind).cmp(&(&b.source, b.kind)));
+        stats
+    }
+
+    /// Changes whenever a tracked message is observed.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::proto::{Header, SensorBatch, SystemStatus};
+
+    fn batch(source: &str, seq: u64) -> MessageWrapper {
+        MessageWrapper::SensorBatch(SensorBatch {
+            header: Some(Header {
+                source: source.to_string(),
+                seq,
+                ..Default::default()
+            }),
+            readings: vec![],
+        })
+    }
+
+    fn feed(tracker: &SequenceTracker, seqs: &[u64]) -> Vec<SeqEvent> {
+        seqs.iter()
+            .filter_map(|seq| tracker.observe(&batch("rt", *seq)))
+            .collect()
+    }
+
+    #[test]
+    fn test_gap_then_late_arrival() {
+        let tracker = SequenceTracker::default();
+        assert_eq!(
+            feed(&tracker, &[1, 2, 5, 3, 6]),
+            [
+                SeqEvent::First,
+                SeqEvent::InOrder,
+                SeqEvent::Gap { missing: 2 },
+                SeqEvent::OutOfOrder,
+                SeqEvent::InOrder,
+            ]
+        );
+        let stats = &tracker.stats()[0];
+        assert_eq!((stats.gaps, stats.lost, stats.out_of_order), (1, 1, 1));
+        assert_eq!((stats.received, stats.last_seq), (5, 6));
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let tracker = SequenceTracker::default();
+        assert_eq!(
+            feed(&tracker, &[1, 2, 2, 3, 1]),
+            [
+                SeqEvent::First,
+                SeqEvent::InOrder,
+                SeqEvent::Duplicate,
+                SeqEvent::InOrder,
+                SeqEvent::Duplicate,
+            ]
+        );
+        assert_eq!(tracker.stats()[0].duplicates, 2);
+    }
+
+    #[test]
+    fn test_restart_is_a_reset_not_a_reorder() {
+        let tracker = SequenceTracker::default();
+        feed(&tracker, &[500, 501]);
+        assert_eq!(feed(&tracker, &[1, 2]), [SeqEvent::Reset, SeqEvent::InOrder]);
+        let stats = &tracker.stats()[0];
+        assert_eq!((stats.resets, stats.lost, stats.last_seq), (1, 0, 2));
+    }
+
+    #[test]
+    fn test_streams_are_independent() {
+        let tracker = SequenceTracker::default();
+        tracker.observe(&batch("rt", 1));
+        tracker.observe(&batch("hub", 7));
+        let status = MessageWrapper::SystemStatus(SystemStatus {
+            header: Some(Header {
+                source: "rt".to_string(),
+                seq: 3,
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        assert_eq!(tracker.observe(&status), Some(SeqEvent::First));
+        assert_eq!(tracker.observe(&batch("rt", 0)), None);
+
+        let keys: Vec<(String, MessageKind)> =
+            tracker.stats().into_iter().map(|s| (s.source, s.kind)).collect();
+        assert_eq!(
+            keys,
+            [
+                ("hub".to_string(), MessageKind::SensorBatch),
+                ("rt".to_string(), MessageKind::SensorBatch),
+                ("rt".to_string(), MessageKind::SystemStatus),
+            ]
+        );
+    }
+}
@@ -1,25 +1,110 @@
+use crate::cache::LatestCache;
+use crate::capture::Capture;
+use crate::config::Config;
+use crate::history::History;
+use crate::liveness::LivenessMonitor;
+use crate::peers::PeerTable;
+use crate::recorder::Recorder;
+use crate::replay::Replayer;
+use crate::reliable::DeliveryTracker;
+use crate::sequence::SequenceTracker;
+use crate::timesync::{self, ClockTracker};
+use crate::udp::Outbound;
 use std::sync::Arc;
 use tokio::sync::broadcast;
-use shared::MessageWrapper;
+use shared::ws::{FrameMeta, ServerEvent};
+use shared::{CodecError, MessageWrapper};
+use std::time::SystemTime;
 use dashmap::DashMap;
 
+/// An inbound message with its reception metadata, as broadcast to clients.
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    pub msg: MessageWrapper,
+    pub meta: FrameMeta,
+}
+
+impl Envelope {
+    /// Wraps a message received just now, without a latency estimate.
+    #[cfg(test)]
+    pub fn new(msg: MessageWrapper) -> Self {
+        Self::received_at(msg, SystemTime::now(), None)
+    }
+
+    pub fn received_at(msg: MessageWrapper, at: SystemTime, latency_us: Option<i64>) -> Self {
+        Self {
+            msg,
+            meta: FrameMeta {
+                received_at_us: timesync::to_micros(at),
+                latency_us,
+                replayed: false,
+            },
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct AppState {
+    pub config: Arc<Config>,
     // Broadcast channel for pushing updates to WebSockets
-    pub tx: broadcast::Sender<MessageWrapper>,
-    // Shared state for latest values (optional, for initial state on connection)
-    pub latest_values: Arc<DashMap<u8, MessageWrapper>>,
-    // Channel to send UDP packets (commands)
-    pub udp_tx: tokio::sync::mpsc::Sender<Vec<u8>>,
+    pub tx: broadcast::Sender<Envelope>,
+    // Control events for every WebSocket client, such as node liveness changes
+    pub events: broadcast::Sender<ServerEvent>,
+    // Retained messages replayed to clients on connection, per `Header.qos`
+    pub latest_values: Arc<LatestCache>,
+    // Recent inbound messages for `/api/history`
+    pub history: Arc<History>,
+    // On-disk recording of inbound messages, started and stopped via the API
+    pub recorder: Arc<Recorder>,
+    // Playback of a recording into `tx`, controlled via the API and WebSockets
+    pub replay: Arc<Replayer>,
+    // Channel to send UDP packets (commands), routed by `peers`
+    pub udp_tx: tokio::sync::mpsc::Sender<Outbound>,
+    pub peers: Arc<PeerTable>,
+    // RELIABLE commands awaiting an Ack from the Realtime side
+    pub deliveries: Arc<DeliveryTracker>,
+    // Header.seq accounting per inbound (source, kind) stream
+    pub sequences: Arc<SequenceTracker>,
+    // TimeSync clock estimates and per-source latency
+    pub clocks: Arc<ClockTracker>,
+    // Heartbeat-based liveness of Realtime nodes
+    pub liveness: Arc<LivenessMonitor>,
+    // pcap tap on every UDP datagram, when `capture.path` is set
+    pub capture: Option<Arc<Capture>>,
+    // Inbound UDP frames that failed to decode, keyed by `CodecError::reason`
+    pub dropped_frames: Arc<DashMap<&'static str, u64>>,
 }
 
 impl AppState {
-    pub fn new(udp_tx: tokio::sync::mpsc::Sender<Vec<u8>>) -> Self {
-        let (tx, _rx) = broadcast::channel(100);
+    pub fn new(config: Config, udp_tx: tokio::sync::mpsc::Sender<Outbound>) -> Self {
+        let (tx, _rx) = broadcast::channel(config.channels.broadcast_capacity);
+        let (events, _rx) = broadcast::channel(config.channels.broadcast_capacity);
         Self {
+            peers: Arc::new(PeerTable::from_config(&config)),
+            deliveries: Arc::new(DeliveryTracker::new(config.reliability.clone())),
+            latest_values: Arc::new(LatestCache::new(config.cache.keep_all_limit)),
+            history: Arc::new(History::new(config.history.capacity)),
+            recorder: Arc::new(Recorder::new(config.recorder.clone())),
+            replay: Arc::new(Replayer::default()),
+            clocks: Arc::new(ClockTracker::new(config.timesync.window)),
+            liveness: Arc::new(LivenessMonitor::new(&config.liveness)),
+            capture: config.capture.path.as_deref().and_then(|path| match Capture::create(path, &config.capture) {
+                Ok(capture) => Some(Arc::new(capture)),
+                Err(e) => {
+                    tracing::error!("Could not capture to {}: {}", path.display(), e);
+                    None
+                }
+            }),
+            config: Arc::new(config),
             tx,
-            latest_values: Arc::new(DashMap::new()),
+            events,
             udp_tx,
+            sequences: Arc::new(SequenceTracker::default()),
+            dropped_frames: Arc::new(DashMap::new()),
         }
     }
+
+    pub fn record_drop(&self, err: &CodecError) {
+        *self.dropped_frames.entry(err.reason()).or_insert(0) += 1;
+    }
 }
@@ -0,0 +1,213 @@
+use crate::state::Envelope;
+use shared::MessageKind;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Slowest rate a client may ask for; about one update every 17 minutes.
+pub const MIN_RATE_HZ: f64 = 0.001;
+/// Fastest rate a client may ask for.
+pub const MAX_RATE_HZ: f64 = 10_000.0;
+
+/// Per-client rate limiter with latest-wins coalescing.
+///
+/// Messages of a limited kind go out at most once per interval for each
+/// `(kind, source)` pair; anything arriving in between replaces the pending
+/// message and is counted as coalesced.
+#[derive(Default)]
+pub struct Throttle {
+    intervals: HashMap<MessageKind, Duration>,
+    slots: HashMap<(MessageKind, String), Slot>,
+    coalesced: BTreeMap<MessageKind, u64>,
+}
+
+struct Slot {
+    next_send: Instant,
+    pending: Option<Envelope>,
+}
+
+impl Throttle {
+    /// Limits `kind` to `max_hz` updates per second; `None` or a non-positive
+    /// rate removes the limit. Rates that are not finite or outside
+    /// [`MIN_RATE_HZ`, `MAX_RATE_HZ`] are rejected and leave the limit as it was.
+    pub fn set_rate(&mut self, kind: MessageKind, max_hz: Option<f64>) -> Result<(), String> {
+        match max_hz {
+            Some(hz) if !hz.is_finite() => return Err(format!("max_hz must be finite, got {}", hz)),
+            Some(hz) if hz > 0.0 => {
+                if !(MIN_RATE_HZ..=MAX_RATE_HZ).contains(&hz) {
+                    return Err(format!(
+                        "max_hz must be between {} and {}, got {}",
+                        MIN_RATE_HZ, MAX_RATE_HZ, hz
+                    ));
+                }
+                let interval = Duration::try_from_secs_f64(1.0 / hz).map_err(|e| e.to_string())?;
+                self.intervals.insert(kind, interval);
+            }
+            _ => {
+                self.intervals.remove(&kind);
+                self.slots.retain(|(k, _), _| *k != kind);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the message if it may be sent now, otherwise holds on to it
+    /// until its slot opens.
+    pub fn offer(&mut self, envelope: Envelope, now: Instant) -> Option<Envelope> {
+        let kind = envelope.msg.kind();
+        let Some(&interval) = self.intervals.get(&kind) else {
+            return Some(envelope);
+        };
+        let key = (kind, envelope.msg.source().to_string());
+        match self.slots.get_mut(&key) {
+            Some(slot) if now < slot.next_send || slot.pending.is_some() => {
+                if slot.pending.replace(envelope).is_some() {
+                    *self.coalesced.entry(kind).or_insert(0) += 1;
+                }
+                None
+            }
+            Some(slot) => {
+                slot.next_send = now + interval;
+                Some(envelope)
+            }
+            None => {
+                self.slots.insert(
+                    key,
+                    Slot {
+                        next_send: now + interval,
+                        pending: None,
+                    },
+                );
+                Some(envelope)
+            }
+        }
+    }
+
+    /// Pending messages whose slot has opened by `now`.
+    pub fn due(&mut self, now: Instant) -> Vec<Envelope> {
+        let mut ready = Vec::new();
+        for ((kind, _), slot) in self.slots.iter_mut() {
+            if now >= slot.next_send {
+                if let Some(envelope) = slot.pending.take() {
+                    slot.next_send = now + self.intervals[kind];
+                    ready.push(envelope);
+                }
+            }
+        }
+        ready
+    }
+
+    /// Earliest time a pending message becomes due.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.slots
+            .values()
+            .filter(|slot| slot.pending.is_some())
+            .map(|slot| slot.next_send)
+            .min()
+    }
+
+    /// Coalesced counts since the last call.
+    pub fn take_coalesced(&mut self) -> BTreeMap<MessageKind, u64> {
+        std::mem::take(&mut self.coalesced)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::proto::{Header, SensorBatch, SystemStatus};
+    use shared::MessageWrapper;
+
+    fn batch(source: &str, seq: u64) -> Envelope {
+        Envelope::new(MessageWrapper::SensorBatch(SensorBatch {
+            header: Some(Header {
+                source: source.to_string(),
+                seq,
+                ..Default::default()
+            }),
+            readings: vec![],
+        }))
+    }
+
+    fn seq(envelope: &Envelope) -> u64 {
+        envelope.msg.header().map(|h| h.seq).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_unlimited_kinds_pass_through() {
+        let mut throttle = Throttle::default();
+        let now = Instant::now();
+        throttle.set_rate(MessageKind::SensorBatch, Some(1.0)).unwrap();
+
+        let status = Envelope::new(MessageWrapper::SystemStatus(SystemStatus::default()));
+        assert!(throttle.offer(status.clone(), now).is_some());
+        assert!(throttle.offer(status, now).is_some());
+    }
+
+    #[test]
+    fn test_latest_wins() {
+        let mut throttle = Throttle::default();
+        let now = Instant::now();
+        throttle.set_rate(MessageKind::SensorBatch, Some(2.0)).unwrap();
+
+        assert_eq!(throttle.offer(batch("rt", 1), now).map(|m| seq(&m)), Some(1));
+        assert!(throttle.offer(batch("rt", 2), now).is_none());
+        assert!(throttle.offer(batch("rt", 3), now).is_none());
+        assert!(throttle.due(now).is_empty());
+        assert_eq!(throttle.next_deadline(), Some(now + Duration::from_millis(500)));
+
+        let later = now + Duration::from_millis(500);
+        let ready = throttle.due(later);
+        assert_eq!(ready.iter().map(seq).collect::<Vec<_>>(), [3]);
+        assert_eq!(throttle.take_coalesced().get(&MessageKind::SensorBatch), Some(&1));
+        assert!(throttle.take_coalesced().is_empty());
+    }
+
+    #[test]
+    fn test_sources_are_throttled_independently() {
+        let mut throttle = Throttle::default();
+        let now = Instant::now();
+        throttle.set_rate(MessageKind::SensorBatch, Some(1.0)).unwrap();
+
+        assert!(throttle.offer(batch("hub", 1), now).is_some());
+        assert!(throttle.offer(batch("motion", 1), now).is_some());
+        assert!(throttle.offer(batch("hub", 2), now).is_none());
+    }
+
+    #[test]
+    fn test_clearing_rate_drops_slots() {
+        let mut throttle = Throttle::default();
+        let now = Instant::now();
+        throttle.set_rate(MessageKind::SensorBatch, Some(1.0)).unwrap();
+        throttle.offer(batch("rt", 1), now);
+        throttle.offer(batch("rt", 2), now);
+
+        throttle.set_rate(MessageKind::SensorBatch, None).unwrap();
+        assert_eq!(throttle.next_deadline(), None);
+        assert!(throttle.offer(batch("rt", 3), now).is_some());
+    }
+
+    #[test]
+    fn test_out_of_range_rates_are_rejected() {
+        let mut throttle = Throttle::default();
+        let now = Instant::now();
+        throttle.set_rate(MessageKind::SensorBatch, Some(1.0)).unwrap();
+        assert!(throttle.set_rate(MessageKind::SensorBatch, Some(1e-20)).is_err());
+        assert!(throttle.set_rate(MessageKind::SensorBatch, Some(1e12)).is_err());
+
+        // The previous limit stays in effect
+        assert!(throttle.offer(batch("rt", 1), now).is_some());
+        assert!(throttle.offer(batch("rt", 2), now).is_none());
+        assert_eq!(throttle.next_deadline(), Some(now + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_non_finite_rates_are_rejected() {
+        let mut throttle = Throttle::default();
+        for hz in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(throttle.set_rate(MessageKind::SensorBatch, Some(hz)).is_err(), "{}", hz);
+        }
+        assert!(throttle.offer(batch("rt", 1), Instant::now()).is_some());
+        assert!(throttle.offer(batch("rt", 2), Instant::now()).is_some());
+    }
+}
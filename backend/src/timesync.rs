@@ -0,0 +1,327 @@
+//! NTP-style clock synchronisation with Realtime peers over `TimeSync`.
+//!
+//! The backend sends a request stamped with its own clock in `host_time` and
+//! `Header.timestamp` (t1). A peer answers with a `TimeSync` whose
+//! `host_time` echoes t1, whose `Header.timestamp` is its clock when replying
+//! (t3) and whose `monotonic_nanos` is the time it held the request (t3 - t2).
+//! With the reception time t4 this gives the usual estimates:
+//!
+//! ```text
+//! offset = ((t2 - t1) + (t3 - t4)) / 2
+//! rtt    = (t4 - t1) - (t3 - t2)
+//! ```
+
+use crate::peers::BROADCAST_DEST;
+use crate::state::AppState;
+use crate::udp::Outbound;
+use dashmap::DashMap;
+use shared::Timestamp;
+use shared::proto::{Header, TimeSync};
+use shared::ws::ClockStats;
+use shared::MessageWrapper;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// `Header.source` of messages the backend originates.
+pub const BACKEND_SOURCE: &str = "backend";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub offset_us: i64,
+    pub rtt_us: u64,
+}
+
+#[derive(Default)]
+struct SourceClock {
+    samples: VecDeque<Sample>,
+    exchanges: u64,
+    latency_count: u64,
+    latency_sum: i64,
+    latency_last: Option<i64>,
+    latency_max: Option<i64>,
+}
+
+impl SourceClock {
+    /// The sample least disturbed by queueing, as in NTP's clock filter.
+    fn best(&self) -> Option<Sample> {
+        self.samples.iter().min_by_key(|s| s.rtt_us).copied()
+    }
+}
+
+/// Clock estimates and latency statistics keyed by `Header.source`.
+pub struct ClockTracker {
+    sources: DashMap<String, SourceClock>,
+    /// Samples kept per source for the minimum-delay filter.
+    window: usize,
+    next_seq: AtomicU64,
+    generation: AtomicU64,
+}
+
+impl ClockTracker {
+    pub fn new(window: usize) -> Self {
+        Self {
+            sources: DashMap::new(),
+            window,
+            next_seq: AtomicU64::new(1),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    /// A request for every peer, stamped with `now`.
+    pub fn request(&self, now: SystemTime) -> MessageWrapper {
+        let t1 = to_timestamp(now);
+        MessageWrapper::TimeSync(TimeSync {
+            header: Some(Header {
+                source: BACKEND_SOURCE.to_string(),
+                dest: BROADCAST_DEST.to_string(),
+                seq: self.next_seq.fetch_add(1, Ordering::Relaxed),
+                timestamp: Some(t1),
+                ..Default::default()
+            }),
+            host_time: Some(t1),
+            monotonic_nanos: 0,
+        })
+    }
+
+    /// Folds a peer's reply received at `now` into its estimate. Returns
+    /// `None` for `TimeSync` messages that are not replies, and drops
+    /// replies whose times are too far out to compute with.
+    pub fn on_reply(&self, sync: &TimeSync, now: SystemTime) -> Option<Sample> {
+        let header = sync.header.as_ref()?;
+        if header.source.is_empty() || header.source == BACKEND_SOURCE {
+            return None;
+        }
+        let t1 = from_timestamp(sync.host_time.as_ref()?)?;
+        let t3 = from_timestamp(header.timestamp.as_ref()?)?;
+        let held = (sync.monotonic_nanos / 1000) as i64;
+        let t2 = t3.checked_sub(held)?;
+        let t4 = to_micros(now);
+        let offset = t2.checked_sub(t1)?.checked_add(t3.checked_sub(t4)?)?;
+        let sample = Sample {
+            offset_us: offset / 2,
+            rtt_us: t4.checked_sub(t1)?.checked_sub(held)?.max(0) as u64,
+        };
+
+        let mut clock = self.sources.entry(header.source.clone()).or_default();
+        clock.samples.push_back(sample);
+        while clock.samples.len() > self.window {
+            clock.samples.pop_front();
+        }
+        clock.exchanges += 1;
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        Some(sample)
+    }
+
+    /// One-way latency of `msg` received at `now`, from `Header.timestamp`
+    /// mapped onto the backend clock. Needs an estimate for the source;
+    /// timestamps too far out to compute with are ignored.
+    pub fn latency(&self, msg: &MessageWrapper, now: SystemTime) -> Option<i64> {
+        let sent = from_timestamp(msg.header()?.timestamp.as_ref()?)?;
+        let mut clock = self.sources.get_mut(msg.source())?;
+        let offset = clock.best()?.offset_us;
+        let latency = to_micros(now).checked_sub(sent.checked_sub(offset)?)?;
+        let sum = clock.latency_sum.checked_add(latency)?;
+        clock.latency_count += 1;
+        clock.latency_sum = sum;
+        clock.latency_last = Some(latency);
+        clock.latency_max = clock.latency_max.max(Some(latency));
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        Some(latency)
+    }
+
+    pub fn stats(&self) -> Vec<ClockStats> {
+        let mut stats: Vec<ClockStats> = self
+            .sources
+            .iter()
+            .map(|clock| {
+                let best = clock.best();
+                ClockStats {
+                    source: clock.key().clone(),
+                    offset_us: best.map(|s| s.offset_us),
+                    rtt_us: best.map(|s| s.rtt_us),
+                    exchanges: clock.exchanges,
+                    latency_count: clock.latency_count,
+                    latency_last_us: clock.latency_last,
+                    latency_avg_us: (clock.latency_count > 0)
+                        .then(|| clock.latency_sum / clock.latency_count as i64),
+                    latency_max_us: clock.latency_max,
+                }
+            })
+            .collect();
+        stats.sort_by(|a, b| a.source.cmp(&b.source));
+        stats
+    }
+
+    /// Changes whenever an estimate or a latency figure changes.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+}
+
+/// Periodically starts an exchange with every peer.
+pub async fn time_sync_loop(state: AppState) {
+    let period = Duration::from_millis(state.config.timesync.interval_ms);
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        let request = state.clocks.request(SystemTime::now());
+        if let Err(e) = state.udp_tx.send(Outbound::new(request)).await {
+            warn!("Stopping TimeSync, outbound queue closed: {}", e);
+            return;
+        }
+    }
+}
+
+pub fn to_micros(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_micros() as i64,
+        Err(e) => -(e.duration().as_micros() as i64),
+    }
+}
+
+pub fn to_timestamp(time: SystemTime) -> Timestamp {
+    let micros = to_micros(time);
+    Timestamp {
+        seconds: micros.div_euclid(1_000_000),
+        nanos: (micros.rem_euclid(1_000_000) * 1000) as i32,
+    }
+}
+
+/// Microseconds since the Unix epoch, or `None` when they do not fit.
+pub fn from_timestamp(ts: &Timestamp) -> Option<i64> {
+    ts.seconds.checked_mul(1_000_000)?.checked_add(i64::from(ts.nanos) / 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::proto::SensorBatch;
+
+    fn at(micros: i64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_micros(micros as u64)
+    }
+
+    /// Reply from a peer whose clock runs `offset` ahead, with `one_way`
+    /// delay in each direction and `held` processing time.
+    fn reply(t1: i64, offset: i64, one_way: i64, held: i64) -> (TimeSync, SystemTime) {
+        let t3 = t1 + one_way + held + offset;
+        let sync = TimeSync {
+            header: Some(Header {
+                source: "rt".to_string(),
+                timestamp: Some(to_timestamp(at(t3))),
+                ..Default::default()
+            }),
+            host_time: Some(to_timestamp(at(t1))),
+            monotonic_nanos: (held * 1000) as u64,
+        };
+        (sync, at(t1 + 2 * one_way + held))
+    }
+
+    #[test]
+    fn test_timestamp_conversion() {
+        let time = at(1_700_000_000_123_456);
+        assert_eq!(from_timestamp(&to_timestamp(time)), Some(1_700_000_000_123_456));
+    }
+
+    #[test]
+    fn test_overflowing_timestamps_are_dropped() {
+        let huge = Timestamp {
+            seconds: i64::MAX,
+            nanos: 0,
+        };
+        assert_eq!(from_timestamp(&huge), None);
+
+        let tracker = ClockTracker::new(8);
+        let (mut sync, now) = reply(1_000_000, 2_500, 300, 50);
+        sync.header.as_mut().unwrap().timestamp = Some(huge);
+        assert_eq!(tracker.on_reply(&sync, now), None);
+        // Representable, but the offset arithmetic overflows
+        let (mut sync, now) = reply(1_000_000, 2_500, 300, 50);
+        sync.host_time = Some(Timestamp {
+            seconds: i64::MIN / 1_000_000,
+            nanos: 0,
+        });
+        sync.header.as_mut().unwrap().timestamp = Some(Timestamp {
+            seconds: i64::MAX / 1_000_000,
+            nanos: 0,
+        });
+        assert_eq!(tracker.on_reply(&sync, now), None);
+        assert!(tracker.stats().is_empty());
+
+        let (sync, now) = reply(1_000_000, 2_500, 300, 0);
+        tracker.on_reply(&sync, now);
+        let batch = MessageWrapper::SensorBatch(SensorBatch {
+            header: Some(Header {
+                source: "rt".to_string(),
+                timestamp: Some(Timestamp {
+                    seconds: i64::MIN / 1_000_000,
+                    nanos: 0,
+                }),
+                ..Default::default()
+            }),
+            readings: vec![],
+        });
+        assert_eq!(tracker.latency(&batch, now), None);
+        assert_eq!(tracker.stats()[0].latency_count, 0);
+    }
+
+    #[test]
+    fn test_symmetric_exchange_recovers_offset() {
+        let tracker = ClockTracker::new(8);
+        let (sync, now) = reply(1_000_000, 2_500, 300, 50);
+        let sample = tracker.on_reply(&sync, now).unwrap();
+        assert_eq!(sample, Sample { offset_us: 2_500, rtt_us: 600 });
+    }
+
+    #[test]
+    fn test_lowest_delay_sample_wins() {
+        let tracker = ClockTracker::new(2);
+        tracker.on_reply(&reply(1_000_000, 2_000, 100, 0).0, reply(1_000_000, 2_000, 100, 0).1);
+        let (sync, now) = reply(2_000_000, 9_000, 5_000, 0);
+        tracker.on_reply(&sync, now);
+        assert_eq!(tracker.stats()[0].offset_us, Some(2_000));
+
+        // The first sample falls out of the two-sample window
+        let (sync, now) = reply(3_000_000, 7_000, 1_000, 0);
+        tracker.on_reply(&sync, now);
+        assert_eq!(tracker.stats()[0].offset_us, Some(7_000));
+    }
+
+    #[test]
+    fn test_requests_are_not_replies() {
+        let tracker = ClockTracker::new(8);
+        let MessageWrapper::TimeSync(request) = tracker.request(at(1_000)) else {
+            panic!("request is a TimeSync");
+        };
+        assert_eq!(tracker.on_reply(&request, at(2_000)), None);
+    }
+
+    #[test]
+    fn test_latency_uses_offset() {
+        let tracker = ClockTracker::new(8);
+        let batch = |sent: i64| {
+            MessageWrapper::SensorBatch(SensorBatch {
+                header: Some(Header {
+                    source: "rt".to_string(),
+                    timestamp: Some(to_timestamp(at(sent))),
+                    ..Default::default()
+                }),
+                readings: vec![],
+            })
+        };
+        assert_eq!(tracker.latency(&batch(10_000_000), at(10_000_000)), None);
+
+        let (sync, now) = reply(1_000_000, 2_500, 300, 0);
+        tracker.on_reply(&sync, now);
+        // Sent at backend time 10_000_000, i.e. 10_002_500 on the peer clock
+        assert_eq!(tracker.latency(&batch(10_002_500), at(10_000_400)), Some(400));
+        assert_eq!(tracker.latency(&batch(10_002_500), at(10_000_200)), Some(200));
+
+        let stats = &tracker.stats()[0];
+        assert_eq!(stats.latency_avg_us, Some(300));
+        assert_eq!(stats.latency_max_us, Some(400));
+        assert_eq!(stats.exchanges, 1);
+    }
+}
@@ -1,13 +1,21 @@
-use crate::state::AppState;
+use crate::liveness;
+use crate::reliable::Delivery;
+use crate::sequence::SeqEvent;
+use crate::state::{AppState, Envelope};
+use shared::ws::DeliveryStatus;
 use shared::MessageWrapper;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
 
 use tokio::net::UdpSocket;
-use tracing::{error, info, warn};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{Instant, MissedTickBehavior};
+use tracing::{debug, error, info, warn};
 
-pub async fn udp_listener(state: AppState, port: u16) -> std::io::Result<()> {
-    let addr = format!("0.0.0.0:{}", port);
-    let socket = UdpSocket::bind(&addr).await?;
-    info!("UDP Listener started on {}", addr);
+pub async fn udp_listener(state: AppState, addr: SocketAddr) -> std::io::Result<()> {
+    let socket = UdpSocket::bind(addr).await?;
+    let local = socket.local_addr()?;
+    info!("UDP Listener started on {}", local);
 
     let mut buf = [0u8; 65535]; // Max UDP size
 
@@ -15,37 +23,35 @@ pub async fn udp_listener(state: AppState, port: u16) -> std::io::Result<()> {
         match socket.recv_from(&mut buf).await {
             Ok((size, src)) => {
                 let data = &buf[..size];
+                if let Some(capture) = &state.capture {
+                    capture.record(src, local, data, SystemTime::now());
+                }
                 match MessageWrapper::from_bytes(data) {
                     Ok(msg) => {
-                        // Update latest values
-                        // We use a simple mapping based on the message type
-                        // Since we don't have a direct get_id, we can just store it.
-                        // For this hackathon, let's just broadcast. 
-                        // To fix the warning about unused `latest_values`, let's actually use it.
-                        // We need to map the message to an ID.
-                        let id = match &msg {
-                            MessageWrapper::SensorBatch(_) => 1,
-                            MessageWrapper::SystemStatus(_) => 2,
-                            MessageWrapper::HardwareStatus(_) => 3,
-                            MessageWrapper::ClockModulation(_) => 4,
-                            MessageWrapper::TestCase(_) => 5,
-                            MessageWrapper::SimulationState(_) => 6,
-                            MessageWrapper::TestResult(_) => 7,
-                            MessageWrapper::TimeSync(_) => 8,
-                            MessageWrapper::FaultInjection(_) => 9,
-                            MessageWrapper::ActuatorCommand(_) => 10,
-                            MessageWrapper::Heartbeat(_) => 11,
-                            MessageWrapper::Ack(_) => 12,
-                        };
-                        state.latest_values.insert(id, msg.clone());
-
-                        // Broadcast to WebSockets
-                        if let Err(_e) = state.tx.send(msg.clone()) {
-                            // It's okay if no one is listening
-                            // warn!("Failed to broadcast message: {}", e);
+                        let received_at = SystemTime::now();
+                        match &msg {
+                            MessageWrapper::Heartbeat(hb) => {
+                                state.peers.learn(&hb.node_id, src);
+                                if let Some(transition) = state.liveness.record(hb, Instant::now(), received_at) {
+                                    liveness::publish(&state, &transition);
+                                }
+                            }
+                            MessageWrapper::Ack(ack) => {
+                                state.deliveries.ack(ack);
+                            }
+                            MessageWrapper::TimeSync(sync) => {
+                                state.clocks.on_reply(sync, received_at);
+                            }
+                            _ => {}
                         }
+                        let latency = state.clocks.latency(&msg, received_at);
+                        let envelope = Envelope::received_at(msg, received_at, latency);
+                        state.recorder.record(&envelope, data);
+                        ingest(&state, envelope);
                     }
                     Err(e) => {
+                        // Corrupted frames are dropped here so they never reach the dashboards
+                        state.record_drop(&e);
                         warn!("Failed to deserialize packet from {}: {}", src, e);
                     }
                 }
@@ -57,12 +63,120 @@ pub async fn udp_listener(state: AppState, port: u16) -> std::io::Result<()> {
     }
 }
 
-pub async fn udp_sender(target_addr: String, mut rx: tokio::sync::mpsc::Receiver<Vec<u8>>) {
+/// Hands a decoded inbound message to everything downstream of the link:
+/// sequence tracking, the latest-value cache, history and WebSocket clients.
+/// Replayed messages take this path too, but are left out of the link
+/// statistics of the live sources they were recorded from.
+pub fn ingest(state: &AppState, envelope: Envelope) {
+    let msg = &envelope.msg;
+    let observed = if envelope.meta.replayed { None } else { state.sequences.observe(msg) };
+    match observed {
+        Some(SeqEvent::Gap { missing }) => {
+            debug!("{} from {:?}: {} missing before seq {}", msg.kind(), msg.source(), missing, msg.header().map(|h| h.seq).unwrap_or_default());
+        }
+        Some(SeqEvent::Reset) => {
+            info!("{} sequence from {:?} restarted", msg.kind(), msg.source());
+        }
+        _ => {}
+    }
+
+    // Retain for late joiners according to the message's QoS
+    state.latest_values.insert(msg.clone());
+    state.history.push(envelope.clone());
+
+    // Broadcast to WebSockets
+    if let Err(_e) = state.tx.send(envelope) {
+        // It's okay if no one is listening
+        // warn!("Failed to broadcast message: {}", e);
+    }
+}
+
+/// A command queued for the Realtime side. `reply` receives the delivery
+/// outcome of `RELIABLE` commands; best-effort ones are never reported.
+pub struct Outbound {
+    pub msg: MessageWrapper,
+    pub reply: Option<oneshot::Sender<Delivery>>,
+}
+
+impl Outbound {
+    pub fn new(msg: MessageWrapper) -> Self {
+        Self { msg, reply: None }
+    }
+}
+
+pub async fn udp_sender(state: AppState, mut rx: mpsc::Receiver<Outbound>) {
     let socket = UdpSocket::bind("0.0.0.0:0").await.expect("Failed to bind UDP sender socket");
-    
-    while let Some(data) = rx.recv().await {
-        if let Err(e) = socket.send_to(&data, &target_addr).await {
+    let mut retry = tokio::time::interval(Duration::from_millis(state.config.reliability.retry_tick_ms));
+    retry.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            outbound = rx.recv() => match outbound {
+                Some(outbound) => send_outbound(&socket, &state, outbound).await,
+                None => break,
+            },
+            _ = retry.tick() => {
+                for frame in state.deliveries.due(Instant::now()) {
+                    send_frame(&socket, &state, &frame.data, &frame.targets).await;
+                }
+            }
+        }
+    }
+}
+
+async fn send_outbound(socket: &UdpSocket, state: &AppState, outbound: Outbound) {
+    let Outbound { mut msg, reply } = outbound;
+    let kind = msg.kind();
+    let seq = state.deliveries.assign_seq(&mut msg);
+    let prepared = match state.peers.route(&msg) {
+        Ok(targets) => msg.to_bytes().map(|data| (data, targets)).map_err(|e| {
+            error!("Failed to encode outbound {}: {}", msg.type_name(), e);
+            e.to_string()
+        }),
+        Err(e) => {
+            warn!("Dropping outbound {}: {}", msg.type_name(), e);
+            Err(e)
+        }
+    };
+    let (data, targets) = match prepared {
+        Ok(prepared) => prepared,
+        Err(message) => {
+            if let (Some(reply), Some(seq)) = (reply, seq) {
+                let status = DeliveryStatus::Unroutable { message };
+                let _ = reply.send(Delivery { kind, seq, attempts: 0, status });
+            }
+            return;
+        }
+    };
+    // Tracked before sending so an Ack arriving right away finds its command
+    if let Some(seq) = seq {
+        state.deliveries.track(seq, kind, data.clone(), targets.clone(), reply, Instant::now());
+    }
+    send_frame(socket, state, &data, &targets).await;
+}
+
+async fn send_frame(socket: &UdpSocket, state: &AppState, data: &[u8], targets: &[String]) {
+    for target_addr in targets {
+        // Resolved here rather than by `send_to` so the capture has the address
+        let addr = match tokio::net::lookup_host(target_addr.as_str()).await.map(|mut addrs| addrs.next()) {
+            Ok(Some(addr)) => addr,
+            Ok(None) => {
+                error!("Failed to send UDP packet to {}: no address", target_addr);
+                continue;
+            }
+            Err(e) => {
+                error!("Failed to send UDP packet to {}: {}", target_addr, e);
+                continue;
+            }
+        };
+        if let Err(e) = socket.send_to(data, addr).await {
             error!("Failed to send UDP packet to {}: {}", target_addr, e);
+            continue;
+        }
+        if let Some(capture) = &state.capture {
+            if let Ok(local) = socket.local_addr() {
+                capture.record(local, addr, data, SystemTime::now());
+            }
         }
     }
 }
@@ -70,7 +184,9 @@ pub async fn udp_sender(target_addr: String, mut rx: tokio::sync::mpsc::Receiver
 #[cfg(test)]
 mod tests {
     use super::*;
-    use shared::proto::{SensorReading, Header, SensorBatch};
+    use crate::capture;
+    use crate::config::Config;
+    use shared::proto::{ClockModulation, QosProfile, Reliability, SensorReading, Header, SensorBatch};
     use shared::MessageWrapper;
     use std::time::Duration;
 
@@ -78,13 +194,13 @@ mod tests {
     async fn test_udp_listener_integration() {
         // 1. Setup AppState
         let (udp_tx, _udp_rx) = tokio::sync::mpsc::channel(10);
-        let state = AppState::new(udp_tx);
+        let state = AppState::new(Config::default(), udp_tx);
         let rx_state = state.clone();
 
         // 2. Spawn UDP Listener on a test port
         let port = 5555;
         tokio::spawn(async move {
-            if let Err(e) = udp_listener(rx_state, port).await {
+            if let Err(e) = udp_listener(rx_state, ([127, 0, 0, 1], port).into()).await {
                 eprintln!("UDP listener error: {}", e);
             }
         });
@@ -130,7 +246,8 @@ mod tests {
         let received = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await;
         
         match received {
-            Ok(Ok(MessageWrapper::SensorBatch(received_msg))) => {
+            Ok(Ok(Envelope { msg: MessageWrapper::SensorBatch(received_msg), meta })) => {
+                assert!(meta.received_at_us > 0);
                 assert_eq!(received_msg.header.unwrap().source, "test_source");
                 assert_eq!(received_msg.readings.len(), 1);
                 assert_eq!(received_msg.readings[0].scalar, 25.5);
@@ -140,4 +257,191 @@ mod tests {
             Err(_) => panic!("Timed out waiting for message"),
         }
     }
+
+    #[tokio::test]
+    async fn test_udp_listener_drops_corrupted_frames() {
+        let (udp_tx, _udp_rx) = tokio::sync::mpsc::channel(10);
+        let state = AppState::new(Config::default(), udp_tx);
+        let rx_state = state.clone();
+
+        let port = 5556;
+        tokio::spawn(async move {
+            if let Err(e) = udp_listener(rx_state, ([127, 0, 0, 1], port).into()).await {
+                eprintln!("UDP listener error: {}", e);
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let sender = UdpSocket::bind("0.0.0.0:0").await.expect("Failed to bind sender");
+        sender.connect(format!("127.0.0.1:{}", port)).await.expect("Failed to connect");
+        let mut rx = state.tx.subscribe();
+
+        let batch = SensorBatch {
+            header: None,
+            readings: vec![
+                SensorReading {
+                    sensor_id: "temp_1".to_string(),
+                    scalar: 25.5,
+                    ..Default::default()
+                }
+            ],
+        };
+        let good = MessageWrapper::SensorBatch(batch).to_bytes().expect("Failed to encode");
+        let mut corrupted = good.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0xFF;
+
+        sender.send(&corrupted).await.expect("Failed to send");
+        sender.send(&good).await.expect("Failed to send");
+
+        let received = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await;
+        assert!(matches!(received, Ok(Ok(Envelope { msg: MessageWrapper::SensorBatch(_), .. }))));
+        assert_eq!(state.dropped_frames.get("checksum_mismatch").map(|c| *c), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_capture_keeps_both_directions_and_garbage() {
+        let path = std::env::temp_dir().join(format!("udp-capture-{}.pcap", std::process::id()));
+        let peer = UdpSocket::bind("127.0.0.1:0").await.expect("Failed to bind peer");
+        let mut config = Config::default();
+        config.udp.realtime_host = peer.local_addr().unwrap().to_string();
+        config.capture.path = Some(path.clone());
+        let (udp_tx, udp_rx) = tokio::sync::mpsc::channel(10);
+        let state = AppState::new(config, udp_tx.clone());
+        tokio::spawn(udp_sender(state.clone(), udp_rx));
+
+        // Both sockets are bound to wildcard addresses, as by default
+        let port = 5557;
+        let rx_state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = udp_listener(rx_state, ([0, 0, 0, 0], port).into()).await {
+                eprintln!("UDP listener error: {}", e);
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        peer.send_to(b"not a frame", ("127.0.0.1", port)).await.unwrap();
+        let command = MessageWrapper::ClockModulation(ClockModulation::default());
+        let sent = command.to_bytes().unwrap();
+        udp_tx.send(Outbound::new(command)).await.unwrap();
+        let mut buf = [0u8; 2048];
+        tokio::time::timeout(Duration::from_secs(1), peer.recv_from(&mut buf)).await.unwrap().unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        state.capture.as_ref().unwrap().flush();
+
+        // pcap records: 16-byte record header, 20-byte IPv4 header, 8-byte UDP header
+        let bytes = std::fs::read(&path).unwrap();
+        let mut rest = &bytes[24..];
+        let mut packets = Vec::new();
+        while !rest.is_empty() {
+            let len = u32::from_le_bytes(rest[8..12].try_into().unwrap()) as usize;
+            packets.push(rest[16..16 + len].to_vec());
+            rest = &rest[16 + len..];
+        }
+        // The listener and sender capture independently, in either order
+        assert_eq!(packets.len(), 2);
+        packets.sort_by_key(|packet| packet[28..] != b"not a frame"[..]);
+        assert_eq!(&packets[0][28..], b"not a frame");
+        assert_eq!(&packets[0][22..24], &port.to_be_bytes());
+        assert_eq!(&packets[1][28..], &sent[..]);
+        assert_eq!(&packets[1][22..24], &peer.local_addr().unwrap().port().to_be_bytes());
+        for packet in &packets {
+            assert_eq!((&packet[12..16], &packet[16..20]), (&[127, 0, 0, 1][..], &[127, 0, 0, 1][..]));
+            let udp_len = packet.len() as u32 - 20;
+            let pseudo = capture::sum(&packet[12..20]) + 17 + udp_len;
+            assert_eq!(capture::fold(pseudo + capture::sum(&packet[20..])), 0xffff);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sender_reports_unacked_and_unroutable_commands() {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.expect("Failed to bind peer");
+        let mut config = Config::default();
+        config.udp.realtime_host = peer.local_addr().unwrap().to_string();
+        config.reliability.initial_backoff_ms = 20;
+        config.reliability.deadline_ms = 200;
+        let (udp_tx, udp_rx) = mpsc::channel(10);
+        let state = AppState::new(config, udp_tx.clone());
+        tokio::spawn(udp_sender(state.clone(), udp_rx));
+
+        let command = |dest: &str| {
+            MessageWrapper::ClockModulation(ClockModulation {
+                header: Some(Header {
+                    dest: dest.to_string(),
+                    qos: Some(QosProfile {
+                        reliability: Reliability::Reliable as i32,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+        };
+
+        let (reply_tx, reply_rx) = oneshot::channel();
+        udp_tx
+            .send(Outbound { msg: command(""), reply: Some(reply_tx) })
+            .await
+            .unwrap();
+        let delivery = tokio::time::timeout(Duration::from_secs(1), reply_rx)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(delivery.status, DeliveryStatus::TimedOut);
+        assert!(delivery.attempts > 1);
+        assert_eq!(state.deliveries.pending_count(), 0);
+
+        let (reply_tx, reply_rx) = oneshot::channel();
+        udp_tx
+            .send(Outbound { msg: command("nowhere"), reply: Some(reply_tx) })
+            .await
+            .unwrap();
+        let delivery = reply_rx.await.unwrap();
+        assert!(matches!(delivery.status, DeliveryStatus::Unroutable { .. }));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_immediate_ack_is_not_lost() {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.expect("Failed to bind peer");
+        let mut config = Config::default();
+        config.udp.realtime_host = peer.local_addr().unwrap().to_string();
+        // A lost Ack would time out before any retransmission
+        config.reliability.initial_backoff_ms = 2000;
+        config.reliability.deadline_ms = 500;
+        let (udp_tx, udp_rx) = mpsc::channel(10);
+        let state = AppState::new(config, udp_tx.clone());
+        tokio::spawn(udp_sender(state.clone(), udp_rx));
+
+        let ack_state = state.clone();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 2048];
+            while let Ok((len, _)) = peer.recv_from(&mut buf).await {
+                let msg = MessageWrapper::from_bytes(&buf[..len]).unwrap();
+                ack_state.deliveries.ack(&shared::proto::Ack {
+                    ok: true,
+                    message: String::new(),
+                    seq: msg.header().unwrap().seq,
+                });
+            }
+        });
+
+        for _ in 0..20 {
+            let msg = MessageWrapper::ClockModulation(ClockModulation {
+                header: Some(Header {
+                    qos: Some(QosProfile {
+                        reliability: Reliability::Reliable as i32,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+            let (reply_tx, reply_rx) = oneshot::channel();
+            udp_tx.send(Outbound { msg, reply: Some(reply_tx) }).await.unwrap();
+            let delivery = reply_rx.await.unwrap();
+            assert!(matches!(delivery.status, DeliveryStatus::Acked { .. }), "{:?}", delivery);
+            assert_eq!(delivery.attempts, 1);
+        }
+    }
 }
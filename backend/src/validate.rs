@@ -0,0 +1,155 @@
+//! Checks on commands from WebSocket and REST clients, applied before they
+//! are handed to the UDP sender.
+
+use crate::config::{CommandVariant, CommandsConfig};
+use shared::proto::actuator_command::Command;
+use shared::proto::ActuatorCommand;
+use shared::MessageWrapper;
+
+/// Returns why `msg` must not be forwarded, if it must not. Only
+/// `ActuatorCommand`, `ClockModulation`, `FaultInjection`, `TestCase` and
+/// `TimeSync` may be sent by clients.
+pub fn check_command(msg: &MessageWrapper, config: &CommandsConfig) -> Result<(), String> {
+    match msg {
+        MessageWrapper::ActuatorCommand(cmd) => check_actuator(cmd, config),
+        MessageWrapper::ClockModulation(clock) => {
+            if !clock.time_scale.is_finite() || clock.time_scale < 0.0 {
+                return Err("timeScale must be a non-negative number".to_string());
+            }
+            Ok(())
+        }
+        MessageWrapper::FaultInjection(fault) => {
+            if fault.fault_id.is_empty() {
+                return Err("faultId is required".to_string());
+            }
+            let valid = |sec: f64| sec.is_finite() && sec >= 0.0;
+            if !valid(fault.duration_sec) || !valid(fault.start_time_sec) {
+                return Err("startTimeSec and durationSec must be non-negative numbers".to_string());
+            }
+            Ok(())
+        }
+        MessageWrapper::TestCase(test) => {
+            if test.test_id.is_empty() {
+                return Err("testId is required".to_string());
+            }
+            Ok(())
+        }
+        MessageWrapper::TimeSync(_) => Ok(()),
+        other => Err(format!("{} may not be sent by clients", other.kind())),
+    }
+}
+
+fn check_actuator(cmd: &ActuatorCommand, config: &CommandsConfig) -> Result<(), String> {
+    if cmd.actuator_id.is_empty() {
+        return Err("actuatorId is required".to_string());
+    }
+    let Some(command) = &cmd.command else {
+        return Err("one of position, velocity, torque, on or value is required".to_string());
+    };
+    let (variant, value) = match *command {
+        Command::Position(v) => (CommandVariant::Position, Some(v)),
+        Command::Velocity(v) => (CommandVariant::Velocity, Some(v)),
+        Command::Torque(v) => (CommandVariant::Torque, Some(v)),
+        Command::On(_) => (CommandVariant::On, None),
+        Command::Value(v) => (CommandVariant::Value, Some(v)),
+    };
+    if value.is_some_and(|v| !v.is_finite()) {
+        return Err(format!("{:?} command for {} is not finite", variant, cmd.actuator_id));
+    }
+    let Some(limits) = config.actuators.get(&cmd.actuator_id) else {
+        if config.reject_unknown_actuators {
+            return Err(format!("Unknown actuator {:?}", cmd.actuator_id));
+        }
+        return Ok(());
+    };
+    if !limits.commands.is_empty() && !limits.commands.contains(&variant) {
+        return Err(format!("{} does not accept {:?} commands", cmd.actuator_id, variant));
+    }
+    if let Some(value) = value {
+        if limits.min.is_some_and(|min| value < min) || limits.max.is_some_and(|max| value > max) {
+            return Err(format!(
+                "{:?} {} for {} is outside [{}, {}]",
+                variant,
+                value,
+                cmd.actuator_id,
+                limits.min.map_or("-inf".to_string(), |v| v.to_string()),
+                limits.max.map_or("inf".to_string(), |v| v.to_string()),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ActuatorLimits;
+    use shared::proto::{ClockModulation, FaultInjection, Heartbeat};
+
+    fn config() -> CommandsConfig {
+        let mut config = CommandsConfig::default();
+        config.actuators.insert(
+            "joint_1".to_string(),
+            ActuatorLimits {
+                min: Some(-1.5),
+                max: Some(1.5),
+                commands: vec![CommandVariant::Position],
+            },
+        );
+        config
+    }
+
+    fn actuator(id: &str, command: Command) -> MessageWrapper {
+        MessageWrapper::ActuatorCommand(ActuatorCommand {
+            actuator_id: id.to_string(),
+            command: Some(command),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_only_allowed_kinds_pass() {
+        let config = config();
+        assert!(check_command(&MessageWrapper::Heartbeat(Heartbeat::default()), &config).is_err());
+        let clock = ClockModulation { time_scale: 2.0, ..Default::default() };
+        assert!(check_command(&MessageWrapper::ClockModulation(clock), &config).is_ok());
+    }
+
+    #[test]
+    fn test_actuator_limits() {
+        let config = config();
+        assert!(check_command(&actuator("joint_1", Command::Position(1.0)), &config).is_ok());
+        assert!(check_command(&actuator("joint_1", Command::Position(1.5)), &config).is_ok());
+        let err = check_command(&actuator("joint_1", Command::Position(2.0)), &config).unwrap_err();
+        assert!(err.contains("outside"), "{}", err);
+        assert!(check_command(&actuator("joint_1", Command::Torque(0.0)), &config).is_err());
+        assert!(check_command(&actuator("joint_1", Command::Position(f64::NAN)), &config).is_err());
+    }
+
+    #[test]
+    fn test_fault_times_must_be_finite() {
+        let config = config();
+        let fault = |start_time_sec, duration_sec| {
+            MessageWrapper::FaultInjection(FaultInjection {
+                fault_id: "f1".to_string(),
+                start_time_sec,
+                duration_sec,
+                ..Default::default()
+            })
+        };
+        assert!(check_command(&fault(0.0, 1.5), &config).is_ok());
+        assert!(check_command(&fault(0.0, -1.0), &config).is_err());
+        assert!(check_command(&fault(0.0, f64::NAN), &config).is_err());
+        assert!(check_command(&fault(0.0, f64::INFINITY), &config).is_err());
+        assert!(check_command(&fault(f64::NAN, 1.0), &config).is_err());
+        assert!(check_command(&fault(f64::INFINITY, 1.0), &config).is_err());
+    }
+
+    #[test]
+    fn test_unknown_actuators() {
+        let mut config = config();
+        assert!(check_command(&actuator("fan", Command::On(true)), &config).is_ok());
+        config.reject_unknown_actuators = true;
+        assert!(check_command(&actuator("fan", Command::On(true)), &config).is_err());
+    }
+}
@@ -0,0 +1,755 @@
+use crate::reliable::{is_reliable, Delivery};
+use crate::state::{AppState, Envelope};
+use crate::udp::Outbound;
+use crate::validate;
+use axum::{
+    extract::{ws::{Message, WebSocket, WebSocketUpgrade}, State},
+    http::HeaderValue,
+    response::IntoResponse,
+};
+use crate::throttle::Throttle;
+use futures::{sink::SinkExt, stream::{SplitSink, StreamExt}};
+use shared::ws::{ClientRequest, FrameMeta, JsonFrame, ServerEvent, Subscription, SUBPROTOCOL_JSON, SUBPROTOCOL_PROTOBUF};
+use shared::MessageWrapper;
+use std::time::Duration;
+use tokio::sync::{broadcast::error::RecvError, mpsc, oneshot};
+use tokio::time::{sleep_until, Instant};
+
+use tracing::{error, info, warn};
+
+/// Encoding of `MessageWrapper` frames negotiated via `Sec-WebSocket-Protocol`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireFormat {
+    Protobuf,
+    Json,
+}
+
+impl WireFormat {
+    fn from_protocol(protocol: Option<&HeaderValue>) -> Self {
+        match protocol.and_then(|p| p.to_str().ok()) {
+            Some(SUBPROTOCOL_JSON) => WireFormat::Json,
+            _ => WireFormat::Protobuf,
+        }
+    }
+
+    /// Reception metadata, latency included, is JSON-only: binary frames are
+    /// plain `MessageWrapper` frames, and those clients read latency from
+    /// `ClockStats` events instead.
+    fn encode(self, msg: &MessageWrapper, meta: Option<FrameMeta>) -> Result<Message, String> {
+        match self {
+            WireFormat::Protobuf => msg.to_bytes().map(Message::Binary).map_err(|e| e.to_string()),
+            WireFormat::Json => {
+                let frame = JsonFrame { msg: msg.clone(), meta };
+                serde_json::to_string(&frame).map(Message::Text).map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+fn event_message(event: &ServerEvent) -> Option<Message> {
+    match serde_json::to_string(event) {
+        Ok(text) => Some(Message::Text(text)),
+        Err(e) => {
+            error!("Error serializing server event: {}", e);
+            None
+        }
+    }
+}
+
+pub async fn ws_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    ws.protocols([SUBPROTOCOL_PROTOBUF, SUBPROTOCOL_JSON])
+        .on_upgrade(|socket| handle_socket(socket, state))
+}
+
+type WsSender = SplitSink<WebSocket, Message>;
+
+/// Input from the receive side of a connection to its send side.
+enum SessionInput {
+    Request(ClientRequest),
+    Event(ServerEvent),
+}
+
+/// Send-side state of one WebSocket client.
+struct ClientSession {
+    sender: WsSender,
+    format: WireFormat,
+    subscription: Subscription,
+    throttle: Throttle,
+    /// `SequenceTracker::generation` at the last `LinkStats` sent.
+    stats_generation: u64,
+    /// `ClockTracker::generation` at the last `ClockStats` sent.
+    clock_generation: u64,
+}
+
+impl ClientSession {
+    async fn send_event(&mut self, event: &ServerEvent) -> Result<(), axum::Error> {
+        match event_message(event) {
+            Some(frame) => self.sender.send(frame).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn send_msg(&mut self, msg: &MessageWrapper, meta: Option<FrameMeta>) -> Result<(), axum::Error> {
+        match self.format.encode(msg, meta) {
+            Ok(frame) => self.sender.send(frame).await,
+            Err(e) => {
+                error!("Error serializing message: {}", e);
+                Ok(())
+            }
+        }
+    }
+
+    /// Live path: subscription filter, then rate limiting.
+    async fn forward(&mut self, envelope: &Envelope) -> Result<(), axum::Error> {
+        let Some(msg) = self.subscription.filter(&envelope.msg) else {
+            return Ok(());
+        };
+        let envelope = Envelope { msg, meta: envelope.meta };
+        match self.throttle.offer(envelope, Instant::now()) {
+            Some(envelope) => self.send_msg(&envelope.msg, Some(envelope.meta)).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn flush_due(&mut self) -> Result<(), axum::Error> {
+        for envelope in self.throttle.due(Instant::now()) {
+            self.send_msg(&envelope.msg, Some(envelope.meta)).await?;
+        }
+        Ok(())
+    }
+
+    async fn report_coalesced(&mut self) -> Result<(), axum::Error> {
+        let counts = self.throttle.take_coalesced();
+        if counts.is_empty() {
+            return Ok(());
+        }
+        self.send_event(&ServerEvent::Coalesced { counts }).await
+    }
+
+    async fn report_link_stats(&mut self, state: &AppState) -> Result<(), axum::Error> {
+        let generation = state.sequences.generation();
+        if generation == self.stats_generation {
+            return Ok(());
+        }
+        self.stats_generation = generation;
+        let streams = state.sequences.stats();
+        self.send_event(&ServerEvent::LinkStats { streams }).await
+    }
+
+    async fn report_clock_stats(&mut self, state: &AppState) -> Result<(), axum::Error> {
+        let generation = state.clocks.generation();
+        if generation == self.clock_generation {
+            return Ok(());
+        }
+        self.clock_generation = generation;
+        let sources = state.clocks.stats();
+        self.send_event(&ServerEvent::ClockStats { sources }).await
+    }
+
+    /// Sends the cached latest values that pass the subscription.
+    async fn send_snapshot(&mut self, state: &AppState) -> Result<(), axum::Error> {
+        let snapshot: Vec<MessageWrapper> = state
+            .latest_values
+            .snapshot()
+            .iter()
+            .filter_map(|msg| self.subscription.filter(msg))
+            .collect();
+        for msg in snapshot {
+            self.send_msg(&msg, None).await?;
+        }
+        Ok(())
+    }
+
+    /// Current liveness of every known node, for clients that just connected
+    /// or missed transitions.
+    async fn send_node_states(&mut self, state: &AppState) -> Result<(), axum::Error> {
+        for node in state.liveness.list() {
+            let event = ServerEvent::NodeState {
+                node_id: node.node_id,
+                state: node.state,
+                previous: None,
+            };
+            self.send_event(&event).await?;
+        }
+        Ok(())
+    }
+
+    /// Resynchronises a client that fell behind the broadcast channel instead
+    /// of dropping it: fresh snapshot first, then a gap notification.
+    async fn recover_lag(&mut self, skipped: u64, state: &AppState) -> Result<(), axum::Error> {
+        self.send_snapshot(state).await?;
+        self.send_event(&ServerEvent::Gap { skipped }).await
+    }
+
+    async fn handle_request(&mut self, request: ClientRequest, state: &AppState) -> Result<(), axum::Error> {
+        match request {
+            ClientRequest::Subscribe(subscription) => {
+                info!("Client subscription changed: {:?}", subscription);
+                self.subscription = subscription.clone();
+                self.send_event(&ServerEvent::Subscribed { subscription }).await?;
+                // Bring newly subscribed kinds up to date straight away
+                self.send_snapshot(state).await
+            }
+            ClientRequest::SetRate { kind, max_hz } => {
+                match self.throttle.set_rate(kind, max_hz) {
+                    Ok(()) => {
+                        info!("Client rate for {} set to {:?} Hz", kind, max_hz);
+                        self.send_event(&ServerEvent::RateChanged { kind, max_hz }).await
+                    }
+                    Err(message) => self.send_event(&ServerEvent::Error { message }).await,
+                }
+            }
+            ClientRequest::Replay(control) => {
+                info!("Client replay request: {:?}", control);
+                // Every client hears about the outcome through `state.events`
+                match state.replay.control(state, control).await {
+                    Ok(_) => Ok(()),
+                    Err(e) => self.send_event(&ServerEvent::Error { message: e.to_string() }).await,
+                }
+            }
+        }
+    }
+}
+
+async fn handle_socket(socket: WebSocket, state: AppState) {
+    let format = WireFormat::from_protocol(socket.protocol());
+    info!("WebSocket connected ({:?})", format);
+
+    let (sender, mut receiver) = socket.split();
+    let mut rx = state.tx.subscribe();
+    let mut events = state.events.subscribe();
+    let mut session = ClientSession {
+        sender,
+        format,
+        subscription: Subscription::default(),
+        throttle: Throttle::default(),
+        stats_generation: 0,
+        clock_generation: 0,
+    };
+
+    // Send latest values to the new client
+    if let Err(e) = session.send_snapshot(&state).await {
+        error!("Error sending initial state: {}", e);
+        return;
+    }
+    if let Err(e) = session.send_node_states(&state).await {
+        error!("Error sending node states: {}", e);
+        return;
+    }
+
+    // Control requests and per-client notifications produced by the receive side
+    let (input_tx, mut input_rx) = mpsc::channel::<SessionInput>(state.config.websocket.client_queue);
+
+    // Spawn a task to forward broadcast messages and events to this client
+    let send_state = state.clone();
+    let mut send_task = tokio::spawn(async move {
+        let period = Duration::from_millis(send_state.config.websocket.coalesce_report_ms);
+        let mut report = tokio::time::interval_at(Instant::now() + period, period);
+        let stats_period = Duration::from_millis(send_state.config.websocket.stats_report_ms);
+        let mut stats = tokio::time::interval_at(Instant::now() + stats_period, stats_period);
+        loop {
+            let deadline = session.throttle.next_deadline();
+            let result = tokio::select! {
+                msg = rx.recv() => match msg {
+                    Ok(msg) => session.forward(&msg).await,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("WebSocket client lagged, skipped {} messages", skipped);
+                        session.recover_lag(skipped, &send_state).await
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                event = events.recv() => match event {
+                    Ok(event) => session.send_event(&event).await,
+                    // Missed transitions are superseded by the current states
+                    Err(RecvError::Lagged(_)) => session.send_node_states(&send_state).await,
+                    Err(RecvError::Closed) => break,
+                },
+                Some(input) = input_rx.recv() => match input {
+                    SessionInput::Request(request) => session.handle_request(request, &send_state).await,
+                    SessionInput::Event(event) => session.send_event(&event).await,
+                },
+                _ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    session.flush_due().await
+                }
+                _ = report.tick() => session.report_coalesced().await,
+                _ = stats.tick() => match session.report_link_stats(&send_state).await {
+                    Ok(()) => session.report_clock_stats(&send_state).await,
+                    Err(e) => Err(e),
+                },
+            };
+            if let Err(e) = result {
+                error!("Error sending WS message: {}", e);
+                break;
+            }
+        }
+    });
+
+    // Handle incoming messages from this client
+    let udp_tx = state.udp_tx.clone();
+    let recv_state = state.clone();
+    let mut recv_task = tokio::spawn(async move {
+        while let Some(Ok(msg)) = receiver.next().await {
+            let command = match msg {
+                // Decoded so the sender can route it to the right Realtime node
+                Message::Binary(bytes) => MessageWrapper::from_bytes(&bytes)
+                    .map_err(|e| format!("Invalid binary command: {}", e)),
+                Message::Text(text) => match parse_text(&text, format) {
+                    Ok(ClientText::Request(request)) => {
+                        if input_tx.send(SessionInput::Request(request)).await.is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                    Ok(ClientText::Command(command)) => Ok(*command),
+                    Err(message) => Err(message),
+                },
+                Message::Close(_) => {
+                    break;
+                }
+                _ => continue,
+            };
+            let command = command.and_then(|command| {
+                validate::check_command(&command, &recv_state.config.commands).map(|()| command)
+            });
+            let command = match command {
+                Ok(command) => command,
+                Err(message) => {
+                    warn!("Rejected client frame: {}", message);
+                    let _ = input_tx.send(SessionInput::Event(ServerEvent::Error { message })).await;
+                    continue;
+                }
+            };
+            let mut outbound = Outbound::new(command);
+            if is_reliable(&outbound.msg) {
+                // Report the outcome back to this client once the sender resolves it
+                let (reply_tx, reply_rx) = oneshot::channel();
+                outbound.reply = Some(reply_tx);
+                let events = input_tx.clone();
+                tokio::spawn(async move {
+                    if let Ok(Delivery { kind, seq, attempts, status }) = reply_rx.await {
+                        let event = ServerEvent::Delivery { kind, seq, attempts, status };
+                        let _ = events.send(SessionInput::Event(event)).await;
+                    }
+                });
+            }
+            if let Err(e) = udp_tx.send(outbound).await {
+                error!("Error forwarding WS message to UDP: {}", e);
+                break;
+            }
+        }
+    });
+
+    // If any one of the tasks exit, abort the other.
+    tokio::select! {
+        _ = (&mut send_task) => recv_task.abort(),
+        _ = (&mut recv_task) => send_task.abort(),
+    };
+
+    info!("WebSocket connection closed");
+}
+
+/// A decoded text frame: either a control request or, in JSON mode, a command.
+enum ClientText {
+    Request(ClientRequest),
+    Command(Box<MessageWrapper>),
+}
+
+/// Control requests are recognised by their `op` field; anything else must be
+/// a JSON command, which is only accepted on the JSON subprotocol.
+fn parse_text(text: &str, format: WireFormat) -> Result<ClientText, String> {
+    let value: serde_json::Value =
+        serde_json::from_str(text).map_err(|e| format!("Invalid JSON: {}", e))?;
+    if value.get("op").is_some() {
+        return serde_json::from_value(value)
+            .map(ClientText::Request)
+            .map_err(|e| format!("Invalid request: {}", e));
+    }
+    match format {
+        WireFormat::Json => serde_json::from_value(value)
+            .map(|command| ClientText::Command(Box::new(command)))
+            .map_err(|e| format!("Invalid JSON command: {}", e)),
+        WireFormat::Protobuf => Err("Commands must be sent as binary frames".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::app_router;
+    use crate::config::Config;
+    use shared::proto::{Header, Heartbeat, SensorBatch, SystemStatus, TestCase};
+    use shared::ws::Liveness;
+    use shared::MessageKind;
+    use tokio_tungstenite::tungstenite::{self, client::IntoClientRequest};
+
+    async fn spawn_server(state: AppState) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app_router(state)).await.unwrap();
+        });
+        format!("ws://{}/ws", addr)
+    }
+
+    #[tokio::test]
+    async fn test_json_subprotocol() {
+        let (udp_tx, mut udp_rx) = mpsc::channel(10);
+        let state = AppState::new(Config::default(), udp_tx);
+        let url = spawn_server(state.clone()).await;
+
+        let mut request = url.into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("Sec-WebSocket-Protocol", HeaderValue::from_static(SUBPROTOCOL_JSON));
+        let (mut client, response) = tokio_tungstenite::connect_async(request).await.unwrap();
+        assert_eq!(
+            response.headers().get("Sec-WebSocket-Protocol").unwrap(),
+            SUBPROTOCOL_JSON
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Outbound data is tagged JSON
+        state
+            .tx
+            .send(Envelope::new(MessageWrapper::SystemStatus(SystemStatus::default())))
+            .unwrap();
+        let frame = tokio::time::timeout(Duration::from_secs(1), client.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(frame.to_text().unwrap()).unwrap();
+        assert_eq!(json["type"], "SystemStatus");
+        assert!(json["meta"]["received_at_us"].as_i64().unwrap() > 0);
+
+        // Inbound JSON commands are decoded and forwarded to UDP
+        let command = r#"{"type":"TestCase","payload":{"testId":"script"}}"#;
+        client.send(tungstenite::Message::Text(command.into())).await.unwrap();
+        let forwarded = tokio::time::timeout(Duration::from_secs(1), udp_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        match forwarded.msg {
+            MessageWrapper::TestCase(TestCase { test_id, .. }) => assert_eq!(test_id, "script"),
+            other => panic!("Unexpected message forwarded: {:?}", other),
+        }
+
+        // Garbage is answered with an error event
+        client.send(tungstenite::Message::Text("{\"type\":\"Nope\"}".into())).await.unwrap();
+        let frame = tokio::time::timeout(Duration::from_secs(1), client.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let event: ServerEvent = serde_json::from_str(frame.to_text().unwrap()).unwrap();
+        assert!(matches!(event, ServerEvent::Error { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_defaults_to_protobuf() {
+        let (udp_tx, _udp_rx) = mpsc::channel(10);
+        let state = AppState::new(Config::default(), udp_tx);
+        let url = spawn_server(state.clone()).await;
+
+        let (mut client, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        state
+            .tx
+            .send(Envelope::new(MessageWrapper::SystemStatus(SystemStatus::default())))
+            .unwrap();
+        let frame = tokio::time::timeout(Duration::from_secs(1), client.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        match frame {
+            tungstenite::Message::Binary(bytes) => {
+                assert!(matches!(
+                    MessageWrapper::from_bytes(&bytes),
+                    Ok(MessageWrapper::SystemStatus(_))
+                ));
+            }
+            other => panic!("Expected a binary frame, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscription_filters_feed() {
+        let (udp_tx, _udp_rx) = mpsc::channel(10);
+        let state = AppState::new(Config::default(), udp_tx);
+        let url = spawn_server(state.clone()).await;
+
+        let (mut client, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        let request = r#"{"op":"subscribe","kinds":["SystemStatus"]}"#;
+        client.send(tungstenite::Message::Text(request.into())).await.unwrap();
+
+        let frame = tokio::time::timeout(Duration::from_secs(1), client.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let event: ServerEvent = serde_json::from_str(frame.to_text().unwrap()).unwrap();
+        assert!(matches!(event, ServerEvent::Subscribed { .. }));
+
+        state
+            .tx
+            .send(Envelope::new(MessageWrapper::Heartbeat(Heartbeat::default())))
+            .unwrap();
+        state
+            .tx
+            .send(Envelope::new(MessageWrapper::SystemStatus(SystemStatus::default())))
+            .unwrap();
+        let frame = tokio::time::timeout(Duration::from_secs(1), client.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let msg = MessageWrapper::from_bytes(&frame.into_data()).unwrap();
+        assert!(matches!(msg, MessageWrapper::SystemStatus(_)));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_coalesces() {
+        let (udp_tx, _udp_rx) = mpsc::channel(10);
+        let state = AppState::new(Config::default(), udp_tx);
+        let url = spawn_server(state.clone()).await;
+
+        let (mut client, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        let request = r#"{"op":"set_rate","kind":"SensorBatch","max_hz":1e-20}"#;
+        client.send(tungstenite::Message::Text(request.into())).await.unwrap();
+        let frame = tokio::time::timeout(Duration::from_secs(1), client.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let event: ServerEvent = serde_json::from_str(frame.to_text().unwrap()).unwrap();
+        assert!(matches!(event, ServerEvent::Error { .. }));
+
+        let request = r#"{"op":"set_rate","kind":"SensorBatch","max_hz":5}"#;
+        client.send(tungstenite::Message::Text(request.into())).await.unwrap();
+        let frame = tokio::time::timeout(Duration::from_secs(1), client.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let event: ServerEvent = serde_json::from_str(frame.to_text().unwrap()).unwrap();
+        assert!(matches!(event, ServerEvent::RateChanged { kind: MessageKind::SensorBatch, .. }));
+
+        for seq in 1..=5 {
+            let batch = SensorBatch {
+                header: Some(Header {
+                    seq,
+                    ..Default::default()
+                }),
+                readings: vec![],
+            };
+            state.tx.send(Envelope::new(MessageWrapper::SensorBatch(batch))).unwrap();
+        }
+
+        // First batch immediately, then only the latest one, plus the report
+        let mut seqs = Vec::new();
+        let mut coalesced = None;
+        while coalesced.is_none() || seqs.len() < 2 {
+            let frame = tokio::time::timeout(Duration::from_secs(2), client.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            match frame {
+                tungstenite::Message::Binary(bytes) => {
+                    let msg = MessageWrapper::from_bytes(&bytes).unwrap();
+                    seqs.push(msg.header().unwrap().seq);
+                }
+                tungstenite::Message::Text(text) => {
+                    if let ServerEvent::Coalesced { counts } = serde_json::from_str(&text).unwrap() {
+                        coalesced = counts.get(&MessageKind::SensorBatch).copied();
+                    }
+                }
+                other => panic!("Unexpected frame: {:?}", other),
+            }
+        }
+        assert_eq!(seqs, [1, 5]);
+        assert_eq!(coalesced, Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_lag_resends_snapshot_and_reports_gap() {
+        let (udp_tx, _udp_rx) = mpsc::channel(10);
+        let state = AppState::new(Config::default(), udp_tx);
+        let url = spawn_server(state.clone()).await;
+
+        let (mut client, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let status = MessageWrapper::SystemStatus(SystemStatus {
+            detail: "latest".to_string(),
+            ..Default::default()
+        });
+        state.latest_values.insert(status);
+
+        // Nothing else runs on this runtime until we yield, so the client's
+        // receiver is guaranteed to overflow the broadcast channel (whose
+        // capacity tokio rounds up to a power of two).
+        let capacity = state.config.channels.broadcast_capacity;
+        for _ in 0..(capacity.next_power_of_two() + 20) {
+            let _ = state.tx.send(Envelope::new(MessageWrapper::Heartbeat(Heartbeat::default())));
+        }
+
+        let mut saw_snapshot = false;
+        loop {
+            let frame = tokio::time::timeout(Duration::from_secs(1), client.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            match frame {
+                tungstenite::Message::Binary(bytes) => {
+                    if let Ok(MessageWrapper::SystemStatus(s)) = MessageWrapper::from_bytes(&bytes) {
+                        saw_snapshot |= s.detail == "latest";
+                    }
+                }
+                tungstenite::Message::Text(text) => {
+                    let event: ServerEvent = serde_json::from_str(&text).unwrap();
+                    assert_eq!(event, ServerEvent::Gap { skipped: 20 });
+                    break;
+                }
+                other => panic!("Unexpected frame: {:?}", other),
+            }
+        }
+        assert!(saw_snapshot);
+    }
+
+    #[tokio::test]
+    async fn test_reliable_command_reports_delivery() {
+        let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut config = Config::default();
+        config.udp.realtime_host = peer.local_addr().unwrap().to_string();
+        config.reliability.initial_backoff_ms = 50;
+        let (udp_tx, udp_rx) = mpsc::channel(10);
+        let state = AppState::new(config, udp_tx);
+        tokio::spawn(crate::udp::udp_sender(state.clone(), udp_rx));
+        let url = spawn_server(state.clone()).await;
+
+        let mut request = url.into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("Sec-WebSocket-Protocol", HeaderValue::from_static(SUBPROTOCOL_JSON));
+        let (mut client, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+        let command = r#"{"type":"ActuatorCommand","payload":{"header":{"qos":{"reliability":"RELIABLE"}},"actuatorId":"joint_1","position":1.0}}"#;
+        client.send(tungstenite::Message::Text(command.into())).await.unwrap();
+
+        // The first copy is "lost"; the retransmission carries the same seq
+        let mut buf = [0u8; 2048];
+        let mut seqs = Vec::new();
+        for _ in 0..2 {
+            let (len, _) = tokio::time::timeout(Duration::from_secs(1), peer.recv_from(&mut buf))
+                .await
+                .unwrap()
+                .unwrap();
+            let msg = MessageWrapper::from_bytes(&buf[..len]).unwrap();
+            seqs.push(msg.header().unwrap().seq);
+        }
+        assert_eq!(seqs, [1, 1]);
+        assert!(state.deliveries.ack(&shared::proto::Ack {
+            ok: true,
+            message: "moved".to_string(),
+            seq: 1,
+        }));
+
+        let frame = tokio::time::timeout(Duration::from_secs(1), client.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let event: ServerEvent = serde_json::from_str(frame.to_text().unwrap()).unwrap();
+        assert_eq!(
+            event,
+            ServerEvent::Delivery {
+                kind: MessageKind::ActuatorCommand,
+                seq: 1,
+                attempts: 2,
+                status: shared::ws::DeliveryStatus::Acked {
+                    message: "moved".to_string(),
+                },
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_commands_are_not_forwarded() {
+        let mut config = Config::default();
+        config.commands.actuators.insert(
+            "joint_1".to_string(),
+            crate::config::ActuatorLimits { min: Some(-1.0), max: Some(1.0), ..Default::default() },
+        );
+        let (udp_tx, mut udp_rx) = mpsc::channel(10);
+        let url = spawn_server(AppState::new(config, udp_tx)).await;
+        let (mut client, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+        let send = |msg: MessageWrapper| tungstenite::Message::Binary(msg.to_bytes().unwrap());
+        let heartbeat = MessageWrapper::Heartbeat(Heartbeat::default());
+        let too_far = MessageWrapper::ActuatorCommand(shared::proto::ActuatorCommand {
+            actuator_id: "joint_1".to_string(),
+            command: Some(shared::proto::actuator_command::Command::Position(3.0)),
+            ..Default::default()
+        });
+        for msg in [heartbeat, too_far] {
+            client.send(send(msg)).await.unwrap();
+            match next_event(&mut client).await {
+                ServerEvent::Error { message } => assert!(!message.is_empty()),
+                other => panic!("Unexpected event: {:?}", other),
+            }
+        }
+        client.send(tungstenite::Message::Binary(vec![0xff; 4])).await.unwrap();
+        assert!(matches!(next_event(&mut client).await, ServerEvent::Error { .. }));
+        assert!(udp_rx.try_recv().is_err());
+    }
+
+    type Client = tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >;
+
+    async fn next_event(client: &mut Client) -> ServerEvent {
+        let frame = tokio::time::timeout(Duration::from_secs(1), client.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        serde_json::from_str(frame.to_text().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_node_states_on_connect_and_transition() {
+        let (udp_tx, _udp_rx) = mpsc::channel(10);
+        let state = AppState::new(Config::default(), udp_tx);
+        let heartbeat = Heartbeat {
+            node_id: "motion".to_string(),
+            ..Default::default()
+        };
+        state.liveness.record(&heartbeat, Instant::now(), std::time::SystemTime::now());
+        let url = spawn_server(state.clone()).await;
+
+        let (mut client, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        let current = ServerEvent::NodeState {
+            node_id: "motion".to_string(),
+            state: Liveness::Alive,
+            previous: None,
+        };
+        assert_eq!(next_event(&mut client).await, current);
+
+        let later = Instant::now() + Duration::from_secs(60);
+        for transition in state.liveness.check(later) {
+            crate::liveness::publish(&state, &transition);
+        }
+        let dead = ServerEvent::NodeState {
+            node_id: "motion".to_string(),
+            state: Liveness::Dead,
+            previous: Some(Liveness::Alive),
+        };
+        assert_eq!(next_event(&mut client).await, dead);
+    }
+}
@@ -0,0 +1,286 @@
+//! Generates a Wireshark Lua dissector for the Realtime UDP link.
+//!
+//! The dissector decodes the frame header (or the legacy 1-byte type id),
+//! shows the checksum trailer and hands the payload to Wireshark's protobuf
+//! dissector under the message name the type id maps to. Type ids and names
+//! come from `shared::MessageKind`; the `.proto` file is checked to define
+//! every registered message so the two cannot drift apart unnoticed.
+//!
+//! ```text
+//! cargo run -p dissector_gen -- -o oper.lua
+//! ```
+
+use clap::Parser;
+use shared::models::{FLAG_CRC16, FLAG_CRC32, FRAME_HEADER_LEN, FRAME_MAGIC, PROTO_PACKAGE};
+use shared::MessageKind;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_PROTO: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../shared/proto/operSystem_api_realtime.proto"
+);
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Generate a Wireshark Lua dissector for the Realtime UDP protocol")]
+struct Cli {
+    /// Protobuf definition of the registered messages
+    #[arg(long, default_value = DEFAULT_PROTO)]
+    proto: PathBuf,
+
+    /// UDP ports to register the dissector on
+    #[arg(long, value_delimiter = ',', default_value = "5000,5001")]
+    ports: Vec<u16>,
+
+    /// Output file; standard output when omitted
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let proto = cli.proto.canonicalize().unwrap_or_else(|_| cli.proto.clone());
+    let result = std::fs::read_to_string(&proto)
+        .map_err(|e| format!("{}: {}", proto.display(), e))
+        .and_then(|text| generate(&text, &proto, &cli.ports));
+    let lua = match result {
+        Ok(lua) => lua,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    match &cli.output {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, lua) {
+                eprintln!("{}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+        None => print!("{}", lua),
+    }
+}
+
+/// Package and top-level message names declared by a `.proto` file.
+#[derive(Debug, Default, PartialEq)]
+struct ProtoFile {
+    package: Option<String>,
+    messages: Vec<String>,
+}
+
+/// Just enough of a `.proto` parser to find the package and top-level
+/// messages; nested declarations are skipped by tracking brace depth.
+fn parse_proto(text: &str) -> ProtoFile {
+    let mut proto = ProtoFile::default();
+    let mut depth = 0usize;
+    let mut previous: Option<&str> = None;
+    let code = text
+        .lines()
+        .map(|line| line.split("//").next().unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n")
+        .replace('{', " { ")
+        .replace('}', " } ")
+        .replace(';', " ; ");
+    for token in code.split_whitespace() {
+        match (previous, token) {
+            (_, "{") => depth += 1,
+            (_, "}") => depth = depth.saturating_sub(1),
+            (Some("package"), name) if depth == 0 => proto.package = Some(name.to_string()),
+            (Some("message"), name) if depth == 0 => proto.messages.push(name.to_string()),
+            _ => {}
+        }
+        previous = Some(token);
+    }
+    proto
+}
+
+fn generate(proto_text: &str, proto_path: &Path, ports: &[u16]) -> Result<String, String> {
+    let proto = parse_proto(proto_text);
+    if proto.package.as_deref() != Some(PROTO_PACKAGE) {
+        return Err(format!(
+            "{}: expected package {}, found {:?}",
+            proto_path.display(),
+            PROTO_PACKAGE,
+            proto.package
+        ));
+    }
+    let missing: Vec<&str> = MessageKind::all()
+        .map(MessageKind::name)
+        .filter(|name| !proto.messages.iter().any(|m| m == name))
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!(
+            "{}: registered messages not defined: {}",
+            proto_path.display(),
+            missing.join(", ")
+        ));
+    }
+
+    let proto_dir = proto_path.parent().unwrap_or(Path::new("."));
+    let mut lua = String::new();
+    let _ = write!(
+        lua,
+        r#"-- Wireshark dissector for the operSystem Realtime UDP link.
+-- Generated by dissector_gen from the shared message registry and
+-- {proto}; do not edit.
+--
+-- Install by copying into the Wireshark personal plugins folder. Payloads
+-- are decoded by the protobuf dissector, which needs
+--   {proto_dir}
+-- and the google/protobuf includes under
+-- Preferences > Protocols > Protobuf > Protobuf search paths.
+
+local oper = Proto("oper", "operSystem Realtime link")
+
+local message_names = {{
+"#,
+        proto = proto_path.display(),
+        proto_dir = proto_dir.display(),
+    );
+    for kind in MessageKind::all() {
+        let _ = writeln!(lua, "    [{}] = \"{}\",", kind.id(), kind.name());
+    }
+    lua.push_str("}\n\nlocal message_types = {\n");
+    for kind in MessageKind::all() {
+        let _ = writeln!(lua, "    [{}] = \"{}\",", kind.id(), kind.proto_name());
+    }
+    let _ = write!(
+        lua,
+        r#"}}
+
+local f_magic = ProtoField.string("oper.magic", "Magic")
+local f_version = ProtoField.uint8("oper.version", "Version")
+local f_type = ProtoField.uint8("oper.type", "Message type", base.DEC, message_names)
+local f_flags = ProtoField.uint8("oper.flags", "Flags", base.HEX)
+local f_flag_crc16 = ProtoField.bool("oper.flags.crc16", "CRC-16 trailer", 8, nil, 0x{crc16:02x})
+local f_flag_crc32 = ProtoField.bool("oper.flags.crc32", "CRC-32 trailer", 8, nil, 0x{crc32:02x})
+local f_length = ProtoField.uint32("oper.payload_len", "Payload length")
+local f_checksum = ProtoField.bytes("oper.checksum", "Checksum")
+local f_legacy = ProtoField.bool("oper.legacy", "Legacy 1-byte type id frame")
+oper.fields = {{ f_magic, f_version, f_type, f_flags, f_flag_crc16, f_flag_crc32, f_length, f_checksum, f_legacy }}
+
+local e_unknown_type = ProtoExpert.new("oper.unknown_type", "Unknown message type", expert.group.UNDECODED, expert.severity.WARN)
+local e_truncated = ProtoExpert.new("oper.truncated", "Frame shorter than its header declares", expert.group.MALFORMED, expert.severity.ERROR)
+oper.experts = {{ e_unknown_type, e_truncated }}
+
+local protobuf = Dissector.get("protobuf")
+
+local HEADER_LEN = {header_len}
+
+local function has_flag(flags, flag)
+    return math.floor(flags / flag) % 2 == 1
+end
+
+function oper.dissector(tvb, pinfo, tree)
+    local len = tvb:len()
+    if len == 0 then
+        return 0
+    end
+    pinfo.cols.protocol = "OPER"
+    local subtree = tree:add(oper, tvb())
+
+    local type_id, payload
+    if len >= HEADER_LEN and tvb(0, 2):string() == "{magic}" then
+        subtree:add(f_magic, tvb(0, 2))
+        subtree:add(f_version, tvb(2, 1))
+        subtree:add(f_type, tvb(3, 1))
+        local flags_item = subtree:add(f_flags, tvb(4, 1))
+        flags_item:add(f_flag_crc16, tvb(4, 1))
+        flags_item:add(f_flag_crc32, tvb(4, 1))
+        subtree:add(f_length, tvb(5, 4))
+        type_id = tvb(3, 1):uint()
+        local flags = tvb(4, 1):uint()
+        local payload_len = tvb(5, 4):uint()
+        local trailer = 0
+        if has_flag(flags, 0x{crc16:02x}) then
+            trailer = 2
+        elseif has_flag(flags, 0x{crc32:02x}) then
+            trailer = 4
+        end
+        if HEADER_LEN + payload_len + trailer > len then
+            subtree:add_proto_expert_info(e_truncated)
+            return len
+        end
+        if payload_len > 0 then
+            payload = tvb(HEADER_LEN, payload_len)
+        end
+        if trailer > 0 then
+            subtree:add(f_checksum, tvb(HEADER_LEN + payload_len, trailer))
+        end
+    else
+        subtree:add(f_legacy, true):set_generated()
+        subtree:add(f_type, tvb(0, 1))
+        type_id = tvb(0, 1):uint()
+        if len > 1 then
+            payload = tvb(1)
+        end
+    end
+
+    local name = message_names[type_id]
+    if name == nil then
+        subtree:add_proto_expert_info(e_unknown_type)
+        pinfo.cols.info = "Unknown type " .. type_id
+        return len
+    end
+    subtree:append_text(", " .. name)
+    pinfo.cols.info = name
+    if payload ~= nil then
+        pinfo.private["pb_msg_type"] = "message," .. message_types[type_id]
+        protobuf:call(payload:tvb(), pinfo, tree)
+    end
+    return len
+end
+
+local udp_port = DissectorTable.get("udp.port")
+udp_port:add_for_decode_as(oper)
+"#,
+        crc16 = FLAG_CRC16,
+        crc32 = FLAG_CRC32,
+        header_len = FRAME_HEADER_LEN,
+        magic = String::from_utf8_lossy(&FRAME_MAGIC),
+    );
+    for port in ports {
+        let _ = writeln!(lua, "udp_port:add({}, oper)", port);
+    }
+    Ok(lua)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROTO: &str = include_str!("../../shared/proto/operSystem_api_realtime.proto");
+
+    #[test]
+    fn test_parse_proto_skips_nested_declarations() {
+        let proto = parse_proto(
+            "syntax = \"proto3\";\npackage a.b; // trailing comment\n\
+             message Outer { enum Kind { A = 0; } message Inner { int32 x = 1; } }\n\
+             // message Commented {}\nmessage Next{}",
+        );
+        assert_eq!(proto.package.as_deref(), Some("a.b"));
+        assert_eq!(proto.messages, ["Outer", "Next"]);
+    }
+
+    #[test]
+    fn test_every_registered_message_is_mapped() {
+        let lua = generate(PROTO, Path::new("proto/oper.proto"), &[5000, 6000]).unwrap();
+        for kind in MessageKind::all() {
+            assert!(lua.contains(&format!("[{}] = \"{}\",", kind.id(), kind.proto_name())));
+        }
+        assert!(lua.contains("tvb(0, 2):string() == \"OS\""));
+        assert!(lua.contains("udp_port:add(6000, oper)"));
+        assert!(lua.contains("--   proto\n"));
+    }
+
+    #[test]
+    fn test_proto_must_match_registry() {
+        let renamed = PROTO.replace("message Heartbeat", "message Pulse");
+        let err = generate(&renamed, Path::new("x.proto"), &[]).unwrap_err();
+        assert!(err.contains("Heartbeat"), "{}", err);
+
+        let other = PROTO.replace("package operSystem.api.v1;", "package other;");
+        assert!(generate(&other, Path::new("x.proto"), &[]).is_err());
+    }
+}
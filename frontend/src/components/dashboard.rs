@@ -5,23 +5,42 @@ use crate::components::{
     system_status::SystemStatusPanel,
     control_panel::ControlPanel,
 };
-use shared::{MessageWrapper, proto::{SensorBatch, SystemStatus}};
+use shared::{MessageWrapper, proto::{SensorBatch, SystemStatus}, ws::{Liveness, ServerEvent, StreamStats}};
+use std::collections::BTreeMap;
 
 #[component]
 pub fn Dashboard() -> impl IntoView {
     // Signals for state
-    let (connected, set_connected) = create_signal(false);
+    let (nodes, set_nodes) = create_signal::<BTreeMap<String, Liveness>>(BTreeMap::new());
+    // Connected while at least one Realtime node is heartbeating
+    let connected = Signal::derive(move || nodes.with(|n| n.values().any(|s| *s == Liveness::Alive)));
     let (sensor_data, set_sensor_data) = create_signal::<Option<SensorBatch>>(None);
     let (system_status, set_system_status) = create_signal::<Option<SystemStatus>>(None);
+    let (dropped, set_dropped) = create_signal(0u64);
+    let (link_stats, set_link_stats) = create_signal::<Vec<StreamStats>>(Vec::new());
 
     // WebSocket Service
     let ws_service = WebSocketService::new(move |msg| {
         match msg {
-            MessageWrapper::SensorBatch(batch) => set_sensor_data.set(Some(batch)),
+            // Batches may carry a subset of sensors (the snapshot sends one per
+            // sensor), so merge readings instead of replacing the whole batch
+            MessageWrapper::SensorBatch(batch) => set_sensor_data.update(|data| match data {
+                Some(current) => merge_readings(current, batch),
+                None => *data = Some(batch),
+            }),
             MessageWrapper::SystemStatus(status) => set_system_status.set(Some(status)),
-            MessageWrapper::Heartbeat(_) => set_connected.set(true), // Assume heartbeat means connected
             _ => leptos::logging::log!("Received other message: {:?}", msg),
         }
+    }, move |event| {
+        match event {
+            // We fell behind; the backend resent the snapshot, count what was lost
+            ServerEvent::Gap { skipped } => set_dropped.update(|d| *d += skipped),
+            ServerEvent::LinkStats { streams } => set_link_stats.set(streams),
+            ServerEvent::NodeState { node_id, state, .. } => set_nodes.update(|n| {
+                n.insert(node_id, state);
+            }),
+            _ => leptos::logging::log!("Received server event: {:?}", event),
+        }
     });
 
     // Handle sending commands
@@ -46,7 +65,7 @@ pub fn Dashboard() -> impl IntoView {
             
             <main class="dashboard-grid">
                 <div class="left-panel">
-                    <SystemStatusPanel status=system_status connected=connected />
+                    <SystemStatusPanel status=system_status connected=connected nodes=nodes dropped=dropped link_stats=link_stats />
                     <ControlPanel on_command=send_command />
                 </div>
                 
@@ -57,3 +76,13 @@ pub fn Dashboard() -> impl IntoView {
         </div>
     }
 }
+
+fn merge_readings(current: &mut SensorBatch, batch: SensorBatch) {
+    current.header = batch.header;
+    for reading in batch.readings {
+        match current.readings.iter_mut().find(|r| r.sensor_id == reading.sensor_id) {
+            Some(existing) => *existing = reading,
+            None => current.readings.push(reading),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use leptos::*;
-use shared::proto::SystemStatus;
+use shared::{proto::SystemStatus, ws::{Liveness, StreamStats}};
+use std::collections::BTreeMap;
 
 #[component]
 pub fn SystemStatusPanel(
@@ -7,6 +8,12 @@ pub fn SystemStatusPanel(
     status: Signal<Option<SystemStatus>>,
     #[prop(into)]
     connected: Signal<bool>,
+    #[prop(into)]
+    nodes: Signal<BTreeMap<String, Liveness>>,
+    #[prop(into)]
+    dropped: Signal<u64>,
+    #[prop(into)]
+    link_stats: Signal<Vec<StreamStats>>,
 ) -> impl IntoView {
     view! {
         <div class="system-status card">
@@ -18,6 +25,42 @@ pub fn SystemStatusPanel(
                         {move || if connected.get() { "Connected" } else { "Disconnected" }}
                     </span>
                 </div>
+                {move || {
+                    nodes.get().into_iter().map(|(node_id, state)| {
+                        let class = match state {
+                            Liveness::Alive => "value connected",
+                            Liveness::Stale => "value",
+                            Liveness::Dead => "value disconnected",
+                        };
+                        view! {
+                            <div class="status-row">
+                                <span class="label">{node_id}</span>
+                                <span class=class>{format!("{:?}", state)}</span>
+                            </div>
+                        }
+                    }).collect::<Vec<_>>()
+                }}
+                <Show when=move || { dropped.get() > 0 }>
+                    <div class="status-row">
+                        <span class="label">"Dropped messages"</span>
+                        <span class="value disconnected">{move || dropped.get().to_string()}</span>
+                    </div>
+                </Show>
+                // Only streams with sequence anomalies are worth showing
+                {move || {
+                    link_stats.get().into_iter()
+                        .filter(|s| s.gaps + s.duplicates + s.out_of_order + s.resets > 0)
+                        .map(|s| {
+                            view! {
+                                <div class="status-row">
+                                    <span class="label">{format!("{} {}", s.source, s.kind)}</span>
+                                    <span class="value">
+                                        {format!("lost {} · dup {} · reordered {} · resets {}", s.lost, s.duplicates, s.out_of_order, s.resets)}
+                                    </span>
+                                </div>
+                            }
+                        }).collect::<Vec<_>>()
+                }}
                 {move || {
                     match status.get() {
                         Some(s) => view! {
@@ -2,6 +2,7 @@ use leptos::*;
 use gloo_net::websocket::{futures::WebSocket, Message};
 use futures::{StreamExt, SinkExt};
 use shared::MessageWrapper;
+use shared::ws::{ServerEvent, SUBPROTOCOL_PROTOBUF};
 use wasm_bindgen_futures::spawn_local;
 
 #[derive(Clone)]
@@ -10,12 +11,16 @@ pub struct WebSocketService {
 }
 
 impl WebSocketService {
-    pub fn new(on_message: impl Fn(MessageWrapper) + 'static + Clone) -> Self {
+    pub fn new(
+        on_message: impl Fn(MessageWrapper) + 'static + Clone,
+        on_event: impl Fn(ServerEvent) + 'static + Clone,
+    ) -> Self {
         let (sender, receiver) = futures::channel::mpsc::channel::<Vec<u8>>(100);
         let receiver = std::rc::Rc::new(std::cell::RefCell::new(Some(receiver)));
         
         create_effect(move |_| {
             let on_message = on_message.clone();
+            let on_event = on_event.clone();
             let receiver = receiver.clone();
             let location = web_sys::window().unwrap().location();
             let protocol = if location.protocol().unwrap() == "https:" { "wss" } else { "ws" };
@@ -23,7 +28,7 @@ impl WebSocketService {
             let ws_url = format!("{}://{}/ws", protocol, host);
 
             spawn_local(async move {
-                match WebSocket::open(&ws_url) {
+                match WebSocket::open_with_protocol(&ws_url, SUBPROTOCOL_PROTOBUF) {
                     Ok(ws) => {
                         let (mut write, mut read) = ws.split();
                         
@@ -38,10 +43,19 @@ impl WebSocketService {
 
                         // Handle incoming messages
                         while let Some(msg) = read.next().await {
-                            if let Ok(Message::Bytes(bytes)) = msg {
-                                if let Ok(wrapper) = MessageWrapper::from_bytes(&bytes) {
-                                    on_message(wrapper);
+                            match msg {
+                                Ok(Message::Bytes(bytes)) => {
+                                    if let Ok(wrapper) = MessageWrapper::from_bytes(&bytes) {
+                                        on_message(wrapper);
+                                    }
                                 }
+                                // Control events always arrive as JSON text frames
+                                Ok(Message::Text(text)) => {
+                                    if let Ok(event) = serde_json::from_str::<ServerEvent>(&text) {
+                                        on_event(event);
+                                    }
+                                }
+                                Err(_) => {}
                             }
                         }
                     }
@@ -26,10 +26,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     let mut interval = tokio::time::interval(Duration::from_millis(100)); // 10Hz
     let mut seq = 0;
+    // Header.seq is tracked per (source, kind), so statuses count separately
+    let mut status_seq = 0;
     let start_time = SystemTime::now();
 
+    let mut buf = [0u8; 65535];
+
     loop {
-        interval.tick().await;
+        tokio::select! {
+            received = socket.recv_from(&mut buf) => {
+                match received {
+                    Ok((size, _)) => handle_command(&socket, target, &buf[..size]).await,
+                    Err(e) => error!("Failed to receive command: {}", e),
+                }
+                continue;
+            }
+            _ = interval.tick() => {}
+        }
         seq += 1;
 
         let now = SystemTime::now();
@@ -37,7 +50,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         
         // Create timestamp
         let since_epoch = now.duration_since(UNIX_EPOCH).unwrap();
-        let timestamp = Some(prost_types::Timestamp {
+        let timestamp = Some(shared::Timestamp {
             seconds: since_epoch.as_secs() as i64,
             nanos: since_epoch.subsec_nanos() as i32,
         });
@@ -90,7 +103,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 source: "mock_realtime".to_string(),
                 dest: "backend".to_string(),
                 seq,
-                timestamp: timestamp.clone(),
+                timestamp,
                 frame_id: "world".to_string(),
                 qos: None,
             }),
@@ -111,11 +124,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         // Also send SystemStatus occasionally (every 10th frame, i.e., 1Hz)
         if seq % 10 == 0 {
+            status_seq += 1;
             let status = proto::SystemStatus {
                 header: Some(proto::Header {
                     source: "mock_realtime".to_string(),
                     dest: "backend".to_string(),
-                    seq,
+                    seq: status_seq,
                     timestamp,
                     frame_id: "system".to_string(),
                     qos: None,
@@ -140,6 +154,129 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     error!("Failed to encode status packet: {}", e);
                 }
             }
+
+            // Heartbeat at the same rate so the backend sees this node as alive
+            let heartbeat = MessageWrapper::Heartbeat(proto::Heartbeat {
+                header: Some(proto::Header {
+                    source: "mock_realtime".to_string(),
+                    dest: "backend".to_string(),
+                    seq: status_seq,
+                    timestamp,
+                    frame_id: "system".to_string(),
+                    qos: None,
+                }),
+                node_id: "mock_realtime".to_string(),
+                status: "running".to_string(),
+                uptime_sec: elapsed as u32,
+            });
+            match heartbeat.to_bytes() {
+                Ok(bytes) => {
+                    if let Err(e) = socket.send_to(&bytes, target).await {
+                        error!("Failed to send heartbeat packet: {}", e);
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to encode heartbeat packet: {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Answers commands from the backend. `TimeSync` gets a `TimeSync` reply that
+/// echoes the request time and reports how long the request was held; every
+/// other command is acknowledged with an `Ack` carrying its `Header.seq`.
+async fn handle_command(socket: &UdpSocket, target: SocketAddr, data: &[u8]) {
+    let received_at = SystemTime::now();
+    let msg = match MessageWrapper::from_bytes(data) {
+        Ok(msg) => msg,
+        Err(e) => {
+            error!("Failed to decode command: {}", e);
+            return;
+        }
+    };
+    let MessageWrapper::TimeSync(request) = msg else {
+        info!("Received {}", msg.type_name());
+        if let Some(ack) = ack_for(&msg) {
+            match ack.to_bytes() {
+                Ok(bytes) => {
+                    if let Err(e) = socket.send_to(&bytes, target).await {
+                        error!("Failed to send Ack: {}", e);
+                    }
+                }
+                Err(e) => error!("Failed to encode Ack: {}", e),
+            }
         }
+        return;
+    };
+    let replied_at = SystemTime::now();
+    let since_epoch = replied_at.duration_since(UNIX_EPOCH).unwrap();
+    let reply = MessageWrapper::TimeSync(proto::TimeSync {
+        header: Some(proto::Header {
+            source: "mock_realtime".to_string(),
+            dest: "backend".to_string(),
+            seq: request.header.map(|h| h.seq).unwrap_or_default(),
+            timestamp: Some(shared::Timestamp {
+                seconds: since_epoch.as_secs() as i64,
+                nanos: since_epoch.subsec_nanos() as i32,
+            }),
+            ..Default::default()
+        }),
+        host_time: request.host_time,
+        monotonic_nanos: replied_at.duration_since(received_at).unwrap_or_default().as_nanos() as u64,
+    });
+    match reply.to_bytes() {
+        Ok(bytes) => {
+            if let Err(e) = socket.send_to(&bytes, target).await {
+                error!("Failed to send TimeSync reply: {}", e);
+            }
+        }
+        Err(e) => error!("Failed to encode TimeSync reply: {}", e),
+    }
+}
+
+/// The `Ack` accepting `msg`, or `None` for messages that are not commands.
+fn ack_for(msg: &MessageWrapper) -> Option<MessageWrapper> {
+    let header = msg.header()?;
+    Some(MessageWrapper::Ack(proto::Ack {
+        ok: true,
+        message: format!("{} accepted", msg.type_name()),
+        seq: header.seq,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_commands_are_acked_by_seq() {
+        let mock = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let backend = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let command = MessageWrapper::ActuatorCommand(proto::ActuatorCommand {
+            header: Some(proto::Header {
+                seq: 42,
+                ..Default::default()
+            }),
+            actuator_id: "joint_1".to_string(),
+            ..Default::default()
+        });
+        handle_command(&mock, backend.local_addr().unwrap(), &command.to_bytes().unwrap()).await;
+
+        let mut buf = [0u8; 2048];
+        let (len, _) = tokio::time::timeout(Duration::from_secs(1), backend.recv_from(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        let MessageWrapper::Ack(ack) = MessageWrapper::from_bytes(&buf[..len]).unwrap() else {
+            panic!("expected an Ack");
+        };
+        assert!(ack.ok);
+        assert_eq!(ack.seq, 42);
+    }
+
+    #[test]
+    fn test_acks_are_not_acked() {
+        assert!(ack_for(&MessageWrapper::Ack(proto::Ack::default())).is_none());
     }
 }
@@ -1,10 +1,26 @@
 use std::io::Result;
+use std::path::PathBuf;
 
 fn main() -> Result<()> {
     std::env::set_var("PROTOC", protobuf_src::protoc());
-    prost_build::compile_protos(
-        &["proto/operSystem_api_realtime.proto"],
-        &["proto/"],
-    )?;
+    let descriptor_path = PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("proto_descriptor.bin");
+
+    // Well-known types come from pbjson_types so Duration gets its proto3 JSON
+    // representation; Timestamp is our own for Z-normalized RFC 3339.
+    prost_build::Config::new()
+        .file_descriptor_set_path(&descriptor_path)
+        .compile_well_known_types()
+        .extern_path(".google.protobuf.Timestamp", "crate::timestamp::Timestamp")
+        .extern_path(".google.protobuf", "::pbjson_types")
+        .compile_protos(
+            &["proto/operSystem_api_realtime.proto"],
+            &["proto/"],
+        )?;
+
+    // Serialize/Deserialize impls following the proto3 JSON mapping
+    let descriptor_set = std::fs::read(&descriptor_path)?;
+    pbjson_build::Builder::new()
+        .register_descriptors(&descriptor_set)?
+        .build(&[".operSystem.api.v1"])?;
     Ok(())
 }
@@ -0,0 +1,39 @@
+use crate::models::{MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use thiserror::Error;
+
+/// Errors produced while decoding a `MessageWrapper` frame.
+#[derive(Debug, Error)]
+pub enum CodecError {
+    #[error("Buffer is empty")]
+    Empty,
+    #[error("Unknown message ID: {0}")]
+    UnknownType(u8),
+    #[error("Truncated frame: expected {expected} bytes, got {actual}")]
+    Truncated { expected: usize, actual: usize },
+    #[error("Trailing data after frame: expected {expected} bytes, got {actual}")]
+    TrailingData { expected: usize, actual: usize },
+    #[error("Checksum mismatch")]
+    ChecksumMismatch,
+    #[error("Invalid frame flags: {0:#04x}")]
+    InvalidFlags(u8),
+    #[error("Unsupported protocol version: {0} (supported {MIN_PROTOCOL_VERSION}..={PROTOCOL_VERSION})")]
+    VersionMismatch(u8),
+    #[error("Protobuf decode error: {0}")]
+    Decode(#[from] prost::DecodeError),
+}
+
+impl CodecError {
+    /// Short, stable label for metrics and drop counters.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            CodecError::Empty => "empty",
+            CodecError::UnknownType(_) => "unknown_type",
+            CodecError::Truncated { .. } => "truncated",
+            CodecError::TrailingData { .. } => "trailing_data",
+            CodecError::ChecksumMismatch => "checksum_mismatch",
+            CodecError::InvalidFlags(_) => "invalid_flags",
+            CodecError::VersionMismatch(_) => "version_mismatch",
+            CodecError::Decode(_) => "decode",
+        }
+    }
+}
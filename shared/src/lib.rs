@@ -2,10 +2,18 @@ pub mod oper_system {
     pub mod api {
         pub mod v1 {
             include!(concat!(env!("OUT_DIR"), "/oper_system.api.v1.rs"));
+            include!(concat!(env!("OUT_DIR"), "/oper_system.api.v1.serde.rs"));
         }
     }
 }
 
+pub mod error;
 pub mod models;
-pub use models::MessageWrapper;
+pub mod timestamp;
+pub mod ws;
+pub use error::CodecError;
+pub use models::{Checksum, FrameHeader, MessageKind, MessageWrapper};
 pub use oper_system::api::v1 as proto;
+pub use timestamp::Timestamp;
+// Other well-known types (Duration) used by the generated code
+pub use pbjson_types;
@@ -0,0 +1,85 @@
+//! `google.protobuf.Timestamp` with the canonical proto3 JSON form.
+//!
+//! `pbjson_types::Timestamp` serializes with a `+00:00` offset; the proto3
+//! JSON mapping asks for Z-normalized RFC 3339 with 0, 3, 6 or 9 fractional
+//! digits, which is what this type writes. Parsing accepts any offset.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ::prost::Message)]
+pub struct Timestamp {
+    /// Seconds since the Unix epoch.
+    #[prost(int64, tag = "1")]
+    pub seconds: i64,
+    /// Non-negative fraction of a second, in nanoseconds.
+    #[prost(int32, tag = "2")]
+    pub nanos: i32,
+}
+
+impl TryFrom<Timestamp> for DateTime<Utc> {
+    type Error = &'static str;
+
+    fn try_from(value: Timestamp) -> Result<Self, Self::Error> {
+        let nanos = u32::try_from(value.nanos).map_err(|_| "negative nanos")?;
+        DateTime::from_timestamp(value.seconds, nanos).ok_or("timestamp out of range")
+    }
+}
+
+impl From<DateTime<Utc>> for Timestamp {
+    fn from(value: DateTime<Utc>) -> Self {
+        Self {
+            seconds: value.timestamp(),
+            nanos: value.timestamp_subsec_nanos() as i32,
+        }
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let time: DateTime<Utc> = (*self).try_into().map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(&time.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+    }
+}
+
+struct TimestampVisitor;
+
+impl Visitor<'_> for TimestampVisitor {
+    type Value = Timestamp;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str("an RFC 3339 date string")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        let time = DateTime::parse_from_rfc3339(value).map_err(E::custom)?;
+        Ok(time.with_timezone(&Utc).into())
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(TimestampVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_is_z_normalized() {
+        let whole = Timestamp { seconds: 1_700_000_000, nanos: 0 };
+        assert_eq!(serde_json::to_string(&whole).unwrap(), r#""2023-11-14T22:13:20Z""#);
+        let millis = Timestamp { seconds: 1_700_000_000, nanos: 250_000_000 };
+        assert_eq!(serde_json::to_string(&millis).unwrap(), r#""2023-11-14T22:13:20.250Z""#);
+    }
+
+    #[test]
+    fn test_json_parses_any_offset() {
+        let parsed: Timestamp = serde_json::from_str(r#""2023-11-15T00:13:20.5+02:00""#).unwrap();
+        assert_eq!(parsed, Timestamp { seconds: 1_700_000_000, nanos: 500_000_000 });
+        assert!(serde_json::from_str::<Timestamp>(r#""yesterday""#).is_err());
+    }
+}
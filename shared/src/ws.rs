@@ -0,0 +1,473 @@
+//! WebSocket protocol shared by the backend and the dashboard.
+//!
+//! Data frames carry `MessageWrapper`s, either as binary protobuf frames or as
+//! tagged JSON text frames depending on the negotiated subprotocol. Control
+//! traffic (`ClientRequest`, `ServerEvent`) is always sent as JSON text frames.
+
+use crate::{proto, MessageKind, MessageWrapper};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Binary `MessageWrapper` frames. Also used when the client asks for nothing.
+/// Frames carry no `FrameMeta`; per-source latency arrives in `ClockStats`.
+pub const SUBPROTOCOL_PROTOBUF: &str = "oper.v1.protobuf";
+/// `MessageWrapper` as `{"type": ..., "payload": ...}` JSON text frames, with
+/// per-message `FrameMeta` under `meta`.
+pub const SUBPROTOCOL_JSON: &str = "oper.v1.json";
+
+/// Out-of-band notifications from the backend to a single client.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ServerEvent {
+    /// A frame sent by this client was rejected and not forwarded.
+    Error { message: String },
+    /// The client's subscription is now in effect.
+    Subscribed { subscription: Subscription },
+    /// The client's rate limit for `kind` is now in effect.
+    RateChanged { kind: MessageKind, max_hz: Option<f64> },
+    /// Messages replaced by a newer one while waiting for their rate limit
+    /// slot, per kind, since the previous report.
+    Coalesced { counts: BTreeMap<MessageKind, u64> },
+    /// Final outcome of a `RELIABLE` command sent by this client.
+    Delivery {
+        kind: MessageKind,
+        seq: u64,
+        attempts: u32,
+        #[serde(flatten)]
+        status: DeliveryStatus,
+    },
+    /// The client fell behind the live feed and `skipped` messages were lost.
+    /// The latest snapshot has been resent just before this event.
+    Gap { skipped: u64 },
+    /// Sequence statistics of the Realtime link, sent periodically when they
+    /// have changed.
+    LinkStats { streams: Vec<StreamStats> },
+    /// Clock offset, round trip and message latency per Realtime source, sent
+    /// periodically when they have changed.
+    ClockStats { sources: Vec<ClockStats> },
+    /// A Realtime node's liveness changed, or its current liveness when the
+    /// client connects (`previous` is then absent).
+    NodeState {
+        node_id: String,
+        state: Liveness,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        previous: Option<Liveness>,
+    },
+    /// The replay of a recorded session started, stopped or changed state.
+    Replay(ReplayStatus),
+}
+
+/// Liveness of a Realtime node, derived from the age of its last `Heartbeat`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Liveness {
+    Alive,
+    Stale,
+    Dead,
+}
+
+/// What the backend knows about a node from its heartbeats.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeInfo {
+    pub node_id: String,
+    pub state: Liveness,
+    /// `Heartbeat.status` of the last heartbeat.
+    pub status: String,
+    pub uptime_sec: u32,
+    /// Backend wall clock of the last heartbeat, in microseconds since the Unix epoch.
+    pub last_seen_us: i64,
+}
+
+/// Backend metadata for a forwarded message. Only JSON data frames carry it,
+/// as `meta` next to `type` and `payload`; binary frames stay byte-compatible
+/// with the UDP wire format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameMeta {
+    /// Backend wall clock at reception, in microseconds since the Unix epoch.
+    pub received_at_us: i64,
+    /// Reception time minus `Header.timestamp`, corrected by the sender's
+    /// estimated clock offset. Absent until a `TimeSync` exchange succeeded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_us: Option<i64>,
+    /// Played back from a recording rather than received live.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub replayed: bool,
+}
+
+/// A data frame in the JSON subprotocol.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonFrame {
+    #[serde(flatten)]
+    pub msg: MessageWrapper,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<FrameMeta>,
+}
+
+/// `TimeSync` estimates and observed latency for one Realtime source.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClockStats {
+    pub source: String,
+    /// Source clock minus backend clock, from the lowest-delay recent sample.
+    pub offset_us: Option<i64>,
+    pub rtt_us: Option<u64>,
+    /// Successful `TimeSync` exchanges.
+    pub exchanges: u64,
+    pub latency_count: u64,
+    pub latency_last_us: Option<i64>,
+    pub latency_avg_us: Option<i64>,
+    pub latency_max_us: Option<i64>,
+}
+
+/// `Header.seq` accounting for one `(source, kind)` stream received over UDP.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamStats {
+    pub source: String,
+    pub kind: MessageKind,
+    pub last_seq: u64,
+    pub received: u64,
+    /// Jumps forward past one or more missing sequence numbers.
+    pub gaps: u64,
+    /// Sequence numbers skipped by gaps that have not arrived late since.
+    pub lost: u64,
+    pub duplicates: u64,
+    /// Late arrivals of sequence numbers previously counted as lost.
+    pub out_of_order: u64,
+    /// Sequence restarted well below the last one, e.g. the node rebooted.
+    pub resets: u64,
+}
+
+/// How a `RELIABLE` command ended.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// The Realtime node acknowledged with `Ack.ok == true`.
+    Acked { message: String },
+    /// The Realtime node answered with `Ack.ok == false`.
+    Rejected { message: String },
+    /// No `Ack` arrived before the delivery deadline.
+    TimedOut,
+    /// No peer could be found for the command.
+    Unroutable { message: String },
+}
+
+/// Control requests a client sends as JSON text frames, in either subprotocol.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ClientRequest {
+    /// Replaces the client's current subscription.
+    Subscribe(Subscription),
+    /// Caps updates of `kind` to `max_hz` per source, sending only the latest
+    /// value. `None` removes the cap; rates that are not finite or outside
+    /// 0.001 to 10000 Hz are answered with `Error`.
+    SetRate { kind: MessageKind, max_hz: Option<f64> },
+    /// Controls the replay of a recorded session; also `POST /api/replay`.
+    Replay(ReplayControl),
+}
+
+/// Replay of a recorded session into the live feed, and optionally as UDP
+/// packets to a Realtime node. Times are reception times in the recording.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ReplayControl {
+    /// Loads recording `name` and plays it, replacing any current replay.
+    Start {
+        name: String,
+        #[serde(default = "default_speed")]
+        speed: f64,
+        #[serde(default)]
+        looping: bool,
+        /// Node to also send every recorded datagram to, byte for byte: a
+        /// configured peer's `node_id` or `addr`, or the backend's
+        /// `udp.realtime_host`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        target: Option<String>,
+        /// Load without starting playback.
+        #[serde(default)]
+        paused: bool,
+    },
+    Play,
+    Pause,
+    /// Moves to the first record received at or after `position_us`.
+    Seek { position_us: i64 },
+    /// Playback rate relative to the recording, e.g. 2.0 for twice as fast.
+    Speed { factor: f64 },
+    Loop { enabled: bool },
+    Stop,
+}
+
+fn default_speed() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayState {
+    Playing,
+    Paused,
+    /// Reached the end without looping.
+    Finished,
+    Stopped,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayStatus {
+    pub name: String,
+    pub state: ReplayState,
+    /// Reception time of the last record played, or the seek position.
+    pub position_us: i64,
+    /// Reception times of the first and last records.
+    pub start_us: i64,
+    pub end_us: i64,
+    pub speed: f64,
+    pub looping: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    pub records_played: u64,
+}
+
+/// Per-client filter over the live feed. Empty lists match everything.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Subscription {
+    #[serde(default)]
+    pub kinds: Vec<MessageKind>,
+    /// Exact `Header.source` values.
+    #[serde(default)]
+    pub sources: Vec<String>,
+    /// `sensor_id` globs (`*` and `?`) applied to `SensorBatch` readings.
+    #[serde(default)]
+    pub sensors: Vec<String>,
+}
+
+impl Subscription {
+    /// Returns the part of `msg` this subscription wants, if any. Sensor
+    /// batches are trimmed to the matching readings.
+    pub fn filter(&self, msg: &MessageWrapper) -> Option<MessageWrapper> {
+        if !self.kinds.is_empty() && !self.kinds.contains(&msg.kind()) {
+            return None;
+        }
+        if !self.sources.is_empty() && !self.sources.iter().any(|s| s == msg.source()) {
+            return None;
+        }
+        match msg {
+            MessageWrapper::SensorBatch(batch) if !self.sensors.is_empty() => {
+                let readings: Vec<_> = batch
+                    .readings
+                    .iter()
+                    .filter(|r| self.matches_sensor(&r.sensor_id))
+                    .cloned()
+                    .collect();
+                if readings.is_empty() {
+                    return None;
+                }
+                Some(MessageWrapper::SensorBatch(proto::SensorBatch {
+                    header: batch.header.clone(),
+                    readings,
+                }))
+            }
+            _ => Some(msg.clone()),
+        }
+    }
+
+    pub fn matches_sensor(&self, sensor_id: &str) -> bool {
+        self.sensors.is_empty() || self.sensors.iter().any(|p| glob_match(p, sensor_id))
+    }
+}
+
+/// Minimal glob matcher: `*` matches any run of characters, `?` exactly one.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` seen and the text index it is currently covering
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((sp, st)) = star {
+            p = sp + 1;
+            t = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch(source: &str, sensors: &[&str]) -> MessageWrapper {
+        MessageWrapper::SensorBatch(proto::SensorBatch {
+            header: Some(proto::Header {
+                source: source.to_string(),
+                ..Default::default()
+            }),
+            readings: sensors
+                .iter()
+                .map(|id| proto::SensorReading {
+                    sensor_id: id.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("temp_*", "temp_cpu"));
+        assert!(glob_match("*_temp", "main_temp"));
+        assert!(glob_match("s?n*", "sine_wave"));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+        assert!(!glob_match("temp_*", "main_temp"));
+        assert!(!glob_match("s?", "sine"));
+    }
+
+    #[test]
+    fn test_default_subscription_passes_everything() {
+        let sub = Subscription::default();
+        assert!(sub.filter(&batch("rt", &["a", "b"])).is_some());
+        assert!(sub.filter(&MessageWrapper::Ack(proto::Ack::default())).is_some());
+    }
+
+    #[test]
+    fn test_filter_by_kind_and_source() {
+        let sub = Subscription {
+            kinds: vec![MessageKind::SensorBatch],
+            sources: vec!["hub".to_string()],
+            ..Default::default()
+        };
+        assert!(sub.filter(&batch("hub", &["a"])).is_some());
+        assert!(sub.filter(&batch("motion", &["a"])).is_none());
+        assert!(sub.filter(&MessageWrapper::SystemStatus(Default::default())).is_none());
+    }
+
+    #[test]
+    fn test_filter_trims_sensor_batch() {
+        let sub = Subscription {
+            sensors: vec!["temp_*".to_string()],
+            ..Default::default()
+        };
+        match sub.filter(&batch("rt", &["temp_cpu", "sine_wave", "temp_board"])) {
+            Some(MessageWrapper::SensorBatch(b)) => {
+                let ids: Vec<_> = b.readings.iter().map(|r| r.sensor_id.as_str()).collect();
+                assert_eq!(ids, ["temp_cpu", "temp_board"]);
+            }
+            other => panic!("Unexpected filter result: {:?}", other),
+        }
+        assert!(sub.filter(&batch("rt", &["sine_wave"])).is_none());
+    }
+
+    #[test]
+    fn test_rate_request_json() {
+        let json = r#"{"op":"set_rate","kind":"SensorBatch","max_hz":2.5}"#;
+        let request: ClientRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            request,
+            ClientRequest::SetRate {
+                kind: MessageKind::SensorBatch,
+                max_hz: Some(2.5),
+            }
+        );
+
+        let event = ServerEvent::Coalesced {
+            counts: BTreeMap::from([(MessageKind::SensorBatch, 3)]),
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(json, r#"{"event":"coalesced","counts":{"SensorBatch":3}}"#);
+    }
+
+    #[test]
+    fn test_json_frame_meta() {
+        let frame = JsonFrame {
+            msg: MessageWrapper::Heartbeat(proto::Heartbeat::default()),
+            meta: Some(FrameMeta {
+                received_at_us: 5,
+                latency_us: Some(120),
+                replayed: false,
+            }),
+        };
+        let json = serde_json::to_string(&frame).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"Heartbeat","payload":{},"meta":{"received_at_us":5,"latency_us":120}}"#
+        );
+        let parsed: JsonFrame = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.meta, frame.meta);
+        assert!(matches!(parsed.msg, MessageWrapper::Heartbeat(_)));
+    }
+
+    #[test]
+    fn test_node_state_event_json() {
+        let event = ServerEvent::NodeState {
+            node_id: "motion".to_string(),
+            state: Liveness::Stale,
+            previous: Some(Liveness::Alive),
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(
+            json,
+            r#"{"event":"node_state","node_id":"motion","state":"stale","previous":"alive"}"#
+        );
+        assert_eq!(serde_json::from_str::<ServerEvent>(&json).unwrap(), event);
+    }
+
+    #[test]
+    fn test_delivery_event_json() {
+        let event = ServerEvent::Delivery {
+            kind: MessageKind::ActuatorCommand,
+            seq: 9,
+            attempts: 2,
+            status: DeliveryStatus::Acked {
+                message: "done".to_string(),
+            },
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(
+            json,
+            r#"{"event":"delivery","kind":"ActuatorCommand","seq":9,"attempts":2,"status":"acked","message":"done"}"#
+        );
+        assert_eq!(serde_json::from_str::<ServerEvent>(&json).unwrap(), event);
+    }
+
+    #[test]
+    fn test_subscribe_request_json() {
+        let json = r#"{"op":"subscribe","kinds":["SystemStatus"],"sensors":["temp_*"]}"#;
+        let request: ClientRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            request,
+            ClientRequest::Subscribe(Subscription {
+                kinds: vec![MessageKind::SystemStatus],
+                sources: vec![],
+                sensors: vec!["temp_*".to_string()],
+            })
+        );
+    }
+
+    #[test]
+    fn test_replay_request_json() {
+        let json = r#"{"op":"replay","action":"start","name":"bench","looping":true}"#;
+        let request: ClientRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            request,
+            ClientRequest::Replay(ReplayControl::Start {
+                name: "bench".to_string(),
+                speed: 1.0,
+                looping: true,
+                target: None,
+                paused: false,
+            })
+        );
+        let seek = ClientRequest::Replay(ReplayControl::Seek { position_us: 5 });
+        let json = serde_json::to_string(&seek).unwrap();
+        assert_eq!(json, r#"{"op":"replay","action":"seek","position_us":5}"#);
+        assert_eq!(serde_json::from_str::<ClientRequest>(&json).unwrap(), seek);
+    }
+}